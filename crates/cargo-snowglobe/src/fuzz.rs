@@ -1,12 +1,16 @@
+use std::collections::BTreeMap;
 use std::num::NonZero;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::AtomicU64;
 use std::sync::{Arc, mpsc};
 use std::time::{Duration, Instant};
-use std::{process, thread};
+use std::{env, fs, process, thread};
 
 use anyhow::bail;
 use rand::Rng;
 use rand::seq::IndexedRandom;
+use snowglobe_proto as proto;
+use snowglobe_proto::Message as _;
 
 use crate::scene_bundle::SceneBundle;
 
@@ -15,6 +19,7 @@ pub fn fuzz(
     scenes: &[String],
     jobs: NonZero<usize>,
     runs: u64,
+    events_path: Option<&Path>,
 ) -> anyhow::Result<()> {
     let work = Work::new(runs);
     let (tx, rx) = mpsc::channel();
@@ -24,9 +29,10 @@ pub fn fuzz(
         let scenes = scenes.to_vec();
         let work = work.clone();
         let tx = tx.clone();
+        let keep_events = events_path.is_some();
 
         thread::spawn(move || {
-            if let Err(error) = run_job(&bundle, &scenes, work, tx) {
+            if let Err(error) = run_job(i, &bundle, &scenes, work, keep_events, tx) {
                 eprintln!("error in job {i}: {error}; aborting");
                 process::abort();
             }
@@ -35,14 +41,22 @@ pub fn fuzz(
 
     drop(tx);
 
+    let mut coverage = Coverage::default();
+
     while let Ok(result) = rx.recv() {
         let RunResult {
             seed,
             scene,
             duration,
             output,
+            report,
+            events,
         } = result;
 
+        if let Some(report) = report {
+            coverage.add(report);
+        }
+
         if output.status.success() {
             eprintln!("ran scene {scene} in {duration:?}");
         } else {
@@ -55,10 +69,16 @@ pub fn fuzz(
             eprintln!("--- stderr ---");
             eprintln!("{}", String::from_utf8_lossy(&output.stderr));
             eprintln!();
+            if let (Some(path), Some(events)) = (events_path, events) {
+                fs::write(path, events)?;
+                eprintln!("events written to {}", path.display());
+            }
             bail!("fuzzing encountered a failed run");
         }
     }
 
+    coverage.print_summary();
+
     Ok(())
 }
 
@@ -67,6 +87,9 @@ struct RunResult {
     scene: String,
     duration: Duration,
     output: process::Output,
+    report: Option<proto::Report>,
+    /// Events of the run, kept if it failed.
+    events: Option<Vec<u8>>,
 }
 
 #[derive(Clone)]
@@ -85,32 +108,130 @@ impl Work {
 }
 
 fn run_job(
+    id: usize,
     bundle: &SceneBundle,
     scenes: &[String],
     work: Work,
+    keep_events: bool,
     tx: mpsc::Sender<RunResult>,
 ) -> anyhow::Result<()> {
     let mut rng = rand::rng();
+    let report_path = temp_path("report", id);
+    let events_path = keep_events.then(|| temp_path("events", id));
 
     while work.take() {
         let scene = scenes.choose(&mut rng).unwrap();
         let seed = rng.random();
 
+        let _ = fs::remove_file(&report_path);
+        if let Some(path) = &events_path {
+            let _ = fs::remove_file(path);
+        }
+
         let start = Instant::now();
-        let proc = bundle.run(scene, seed, None)?;
+        let proc = bundle.run(
+            scene,
+            seed,
+            None,
+            Some(&report_path),
+            events_path.as_deref(),
+        )?;
         let output = proc.wait_with_output()?;
         let duration = start.elapsed();
 
+        let events = match &events_path {
+            Some(path) if !output.status.success() => fs::read(path).ok(),
+            _ => None,
+        };
+
+        let report = match fs::read(&report_path).map(|b| proto::Report::deserialize(&b)) {
+            Ok(Ok(report)) => Some(report),
+            Ok(Err(error)) => {
+                eprintln!("malformed report from scene {scene} with seed {seed}: {error}");
+                None
+            }
+            Err(_) => None,
+        };
+
         let result = RunResult {
             seed,
             scene: scene.to_string(),
             duration,
             output,
+            report,
+            events,
         };
         if tx.send(result).is_err() {
             break;
         }
     }
 
+    let _ = fs::remove_file(&report_path);
+    if let Some(path) = &events_path {
+        let _ = fs::remove_file(path);
+    }
+
     Ok(())
 }
+
+fn temp_path(kind: &str, job_id: usize) -> PathBuf {
+    let name = format!("snowglobe-{kind}-{}-{job_id}.json", process::id());
+    env::temp_dir().join(name)
+}
+
+/// Property coverage aggregated over all runs of a campaign.
+#[derive(Default)]
+struct Coverage {
+    runs: u64,
+    properties: BTreeMap<String, proto::Property>,
+}
+
+impl Coverage {
+    fn add(&mut self, report: proto::Report) {
+        self.runs += 1;
+
+        for prop in report.properties {
+            let key = format!("{} {}", prop.location, prop.message);
+            self.properties
+                .entry(key)
+                .and_modify(|p| {
+                    p.hits += prop.hits;
+                    p.passes += prop.passes;
+                })
+                .or_insert(prop);
+        }
+    }
+
+    fn print_summary(&self) {
+        use proto::PropertyKind::*;
+
+        if self.properties.is_empty() {
+            return;
+        }
+
+        let unreached: Vec<_> = self
+            .properties
+            .values()
+            .filter(|p| match p.kind {
+                Always => p.hits == 0,
+                Sometimes | Reachable => p.passes == 0,
+            })
+            .collect();
+
+        eprintln!();
+        eprintln!(
+            "{} of {} properties unreached after {} runs",
+            unreached.len(),
+            self.properties.len(),
+            self.runs,
+        );
+        for prop in unreached {
+            let kind = match prop.kind {
+                Always => "always",
+                Sometimes => "sometimes",
+                Reachable => "reachable",
+            };
+            eprintln!("    {kind} \"{}\" ({})", prop.message, prop.location);
+        }
+    }
+}
@@ -65,11 +65,11 @@ enum Command {
     /// Fuzz one or all scenes
     Fuzz(FuzzArgs),
     /// Check determinism of a scene run
-    CheckDeterminism(RunArgs),
+    CheckDeterminism(SceneArgs),
 }
 
 #[derive(clap::Args)]
-struct RunArgs {
+struct SceneArgs {
     /// Name of the scene
     scene: String,
     /// RNG seed for the simulation
@@ -77,6 +77,15 @@ struct RunArgs {
     rng_seed: Option<u64>,
 }
 
+#[derive(clap::Args)]
+struct RunArgs {
+    #[command(flatten)]
+    scene: SceneArgs,
+    /// File to write simulation events to, one JSON object per line
+    #[arg(long)]
+    events: Option<PathBuf>,
+}
+
 #[derive(clap::Args)]
 struct FuzzArgs {
     /// Name of the scene to fuzz (default: all scenes)
@@ -88,6 +97,9 @@ struct FuzzArgs {
     /// Number of parallel jobs (default: # of CPUs)
     #[arg(long, short)]
     jobs: Option<NonZero<usize>>,
+    /// File to write the simulation events of a failed run to
+    #[arg(long)]
+    events: Option<PathBuf>,
 }
 
 fn main() -> anyhow::Result<()> {
@@ -170,9 +182,10 @@ fn cmd_list(bundle: &SceneBundle) {
 }
 
 fn cmd_run(bundle: &SceneBundle, args: &RunArgs) -> anyhow::Result<()> {
-    let rng_seed = args.rng_seed.unwrap_or_else(rand::random);
+    let rng_seed = args.scene.rng_seed.unwrap_or_else(rand::random);
 
-    let mut proc = bundle.run(&args.scene, rng_seed, None)?;
+    let events = args.events.as_deref();
+    let mut proc = bundle.run(&args.scene.scene, rng_seed, None, None, events)?;
     let stdout = BufReader::new(proc.stdout.take().unwrap());
     let stderr = BufReader::new(proc.stderr.take().unwrap());
 
@@ -210,18 +223,18 @@ fn cmd_fuzz(bundle: &SceneBundle, args: &FuzzArgs) -> anyhow::Result<()> {
     let runs = args.runs.unwrap_or(u64::MAX);
 
     eprintln!("Fuzzing {} scene(s) with {jobs} jobs", scenes.len());
-    fuzz::fuzz(bundle, &scenes, jobs, runs)
+    fuzz::fuzz(bundle, &scenes, jobs, runs, args.events.as_deref())
 }
 
-fn cmd_check_determinism(bundle: &SceneBundle, args: &RunArgs) -> anyhow::Result<()> {
+fn cmd_check_determinism(bundle: &SceneBundle, args: &SceneArgs) -> anyhow::Result<()> {
     let rng_seed = args.rng_seed.unwrap_or_else(rand::random);
     let log_filter = Some("trace");
 
-    let mut proc1 = bundle.run(&args.scene, rng_seed, log_filter)?;
+    let mut proc1 = bundle.run(&args.scene, rng_seed, log_filter, None, None)?;
     let stdout1 = BufReader::new(proc1.stdout.take().unwrap());
     let stderr1 = BufReader::new(proc1.stderr.take().unwrap());
 
-    let mut proc2 = bundle.run(&args.scene, rng_seed, log_filter)?;
+    let mut proc2 = bundle.run(&args.scene, rng_seed, log_filter, None, None)?;
     let stdout2 = BufReader::new(proc2.stdout.take().unwrap());
     let stderr2 = BufReader::new(proc2.stderr.take().unwrap());
 
@@ -1,4 +1,4 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process;
 
 use anyhow::{Context, bail};
@@ -39,11 +39,20 @@ impl SceneBundle {
         scene: &str,
         rng_seed: u64,
         log_filter: Option<&str>,
+        report_path: Option<&Path>,
+        events_path: Option<&Path>,
     ) -> anyhow::Result<process::Child> {
         let mut cmd = process::Command::new(&self.path);
         cmd.args(["run", scene]);
         cmd.args(["--rng-seed", &rng_seed.to_string()]);
 
+        if let Some(path) = report_path {
+            cmd.arg("--report").arg(path);
+        }
+        if let Some(path) = events_path {
+            cmd.arg("--events").arg(path);
+        }
+
         if let Some(filter) = log_filter {
             cmd.env("RUST_LOG", filter);
         }
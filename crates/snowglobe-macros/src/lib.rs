@@ -18,6 +18,16 @@ pub fn scene(args: TokenStream, item: TokenStream) -> TokenStream {
     let max_message_latency = quote_option(args.max_message_latency);
     let fail_rate = quote_option(args.fail_rate);
     let repair_rate = quote_option(args.repair_rate);
+    let epoch = quote_option(args.epoch);
+    let sleep = quote_option(args.sleep);
+    let disk_eio_rate = quote_option(args.disk_eio_rate);
+    let disk_enospc_rate = quote_option(args.disk_enospc_rate);
+    let disk_edquot_rate = quote_option(args.disk_edquot_rate);
+    let disk_short_io_rate = quote_option(args.disk_short_io_rate);
+    let disk_latency = quote_option(args.disk_latency);
+    let env = quote_option(args.env);
+    let cpus = quote_option(args.cpus);
+    let threads = quote_option(args.threads);
 
     let expanded = quote! {
         #func
@@ -38,6 +48,16 @@ pub fn scene(args: TokenStream, item: TokenStream) -> TokenStream {
                     max_message_latency: #max_message_latency,
                     fail_rate: #fail_rate,
                     repair_rate: #repair_rate,
+                    epoch: #epoch,
+                    sleep: #sleep,
+                    disk_eio_rate: #disk_eio_rate,
+                    disk_enospc_rate: #disk_enospc_rate,
+                    disk_edquot_rate: #disk_edquot_rate,
+                    disk_short_io_rate: #disk_short_io_rate,
+                    disk_latency: #disk_latency,
+                    env: #env,
+                    cpus: #cpus,
+                    threads: #threads,
                 },
             };
         };
@@ -62,6 +82,16 @@ struct SceneArgs {
     max_message_latency: Option<DurationArg>,
     fail_rate: Option<f64>,
     repair_rate: Option<f64>,
+    epoch: Option<EpochArg>,
+    sleep: Option<SleepArg>,
+    disk_eio_rate: Option<f64>,
+    disk_enospc_rate: Option<f64>,
+    disk_edquot_rate: Option<f64>,
+    disk_short_io_rate: Option<f64>,
+    disk_latency: Option<DurationArg>,
+    env: Option<EnvArg>,
+    cpus: Option<usize>,
+    threads: Option<ThreadsArg>,
 }
 
 #[derive(Debug)]
@@ -77,10 +107,119 @@ impl darling::FromMeta for DurationArg {
 
 impl quote::ToTokens for DurationArg {
     fn to_tokens(&self, tokens: &mut proc_macro2::TokenStream) {
-        let secs = self.0.as_secs();
-        let nanos = self.0.subsec_nanos();
+        tokens.extend(quote_duration(*self.0));
+    }
+}
+
+/// An RFC 3339 timestamp, stored as the duration since the Unix epoch.
+#[derive(Debug)]
+struct EpochArg(std::time::Duration);
+
+impl darling::FromMeta for EpochArg {
+    fn from_string(s: &str) -> darling::Result<Self> {
+        let time = humantime::parse_rfc3339_weak(s).map_err(darling::Error::custom)?;
+        time.duration_since(std::time::UNIX_EPOCH)
+            .map(EpochArg)
+            .map_err(|_| darling::Error::custom("epoch must not be before 1970"))
+    }
+}
+
+impl quote::ToTokens for EpochArg {
+    fn to_tokens(&self, tokens: &mut proc_macro2::TokenStream) {
+        tokens.extend(quote_duration(self.0));
+    }
+}
+
+/// Behavior of blocking sleeps: `"deny"` or `"advance"`.
+#[derive(Debug)]
+enum SleepArg {
+    Deny,
+    Advance,
+}
+
+impl darling::FromMeta for SleepArg {
+    fn from_string(s: &str) -> darling::Result<Self> {
+        match s {
+            "deny" => Ok(Self::Deny),
+            "advance" => Ok(Self::Advance),
+            _ => Err(darling::Error::unknown_value(s)),
+        }
+    }
+}
+
+impl quote::ToTokens for SleepArg {
+    fn to_tokens(&self, tokens: &mut proc_macro2::TokenStream) {
+        tokens.extend(match self {
+            Self::Deny => quote! { SleepMode::Deny },
+            Self::Advance => quote! { SleepMode::Advance },
+        });
+    }
+}
+
+/// Behavior of `pthread_create`: `"deny"` or `"green"`.
+#[derive(Debug)]
+enum ThreadsArg {
+    Deny,
+    Green,
+}
+
+impl darling::FromMeta for ThreadsArg {
+    fn from_string(s: &str) -> darling::Result<Self> {
+        match s {
+            "deny" => Ok(Self::Deny),
+            "green" => Ok(Self::Green),
+            _ => Err(darling::Error::unknown_value(s)),
+        }
+    }
+}
+
+impl quote::ToTokens for ThreadsArg {
+    fn to_tokens(&self, tokens: &mut proc_macro2::TokenStream) {
+        tokens.extend(match self {
+            Self::Deny => quote! { ThreadMode::Deny },
+            Self::Green => quote! { ThreadMode::Green },
+        });
+    }
+}
+
+/// Environment variables, as an array of `"KEY=VALUE"` strings.
+#[derive(Debug)]
+struct EnvArg(Vec<(String, String)>);
+
+impl darling::FromMeta for EnvArg {
+    fn from_expr(expr: &syn::Expr) -> darling::Result<Self> {
+        let syn::Expr::Array(array) = expr else {
+            return Err(darling::Error::unexpected_expr_type(expr));
+        };
+
+        array
+            .elems
+            .iter()
+            .map(|elem| {
+                let var = String::from_expr(elem)?;
+                match var.split_once('=') {
+                    Some((key, value)) if !key.is_empty() => Ok((key.into(), value.into())),
+                    _ => Err(darling::Error::custom("expected `KEY=VALUE`").with_span(elem)),
+                }
+            })
+            .collect::<darling::Result<_>>()
+            .map(EnvArg)
+    }
+}
+
+impl quote::ToTokens for EnvArg {
+    fn to_tokens(&self, tokens: &mut proc_macro2::TokenStream) {
+        let (keys, values): (Vec<_>, Vec<_>) = self.0.iter().cloned().unzip();
         tokens.extend(quote! {
-            ::std::time::Duration::new(#secs, #nanos)
+            &[#( (#keys, #values) ),*]
         });
     }
 }
+
+fn quote_duration(duration: std::time::Duration) -> proc_macro2::TokenStream {
+    let secs = duration.as_secs();
+    let nanos = duration.subsec_nanos();
+    quote! {
+        ::std::time::Duration::new(#secs, #nanos)
+    }
+}
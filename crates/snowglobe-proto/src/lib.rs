@@ -1,4 +1,5 @@
 use std::fmt;
+use std::time::Duration;
 
 use serde::de::{DeserializeOwned, Error as _};
 use serde::{Deserialize, Serialize};
@@ -66,3 +67,79 @@ impl Message for Info {}
 pub struct Info {
     pub scenes: Vec<String>,
 }
+
+impl Message for Event {}
+
+/// An event that occurred during a simulation run.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Event {
+    /// Simulated time at which the event occurred.
+    pub time: Duration,
+    pub kind: EventKind,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum EventKind {
+    Partition { a: String, b: String },
+    PartitionOneway { from: String, to: String },
+    Repair { a: String, b: String },
+    RepairOneway { from: String, to: String },
+    Hold { a: String, b: String },
+    Release { a: String, b: String },
+    Crash { host: String },
+    Bounce { host: String },
+    Restart { host: String },
+}
+
+impl Message for Report {}
+
+/// Summary of a single simulation run.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Report {
+    pub properties: Vec<Property>,
+}
+
+/// Status of a property assertion site after a run.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Property {
+    pub kind: PropertyKind,
+    pub message: String,
+    /// Source location of the assertion, as `file:line:column`.
+    pub location: String,
+    /// Number of times the assertion was evaluated.
+    pub hits: u64,
+    /// Number of times the asserted condition held.
+    pub passes: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PropertyKind {
+    Always,
+    Sometimes,
+    Reachable,
+}
+
+impl Message for MessageEvent {}
+
+/// A network message observed during a simulation run.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MessageEvent {
+    /// Simulated time at which the message was observed.
+    pub time: Duration,
+    pub kind: MessageEventKind,
+    pub src: String,
+    pub dst: String,
+    /// Description of the message payload.
+    pub protocol: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MessageEventKind {
+    Sent,
+    Delivered,
+    Dropped,
+    Held,
+}
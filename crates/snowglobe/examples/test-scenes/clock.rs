@@ -0,0 +1,173 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use snowglobe::{Clock, Sim};
+use tokio::time::sleep;
+
+/// Read the given clock through `clock_gettime`.
+fn read_clock(clk_id: libc::clockid_t) -> Duration {
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    let ret = unsafe { libc::clock_gettime(clk_id, &mut ts) };
+    assert_eq!(ret, 0, "clock_gettime({clk_id}) failed");
+    Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32)
+}
+
+fn since_epoch() -> Duration {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap()
+}
+
+#[snowglobe::scene]
+fn clock_ids(mut sim: Sim) {
+    assert_eq!(read_clock(libc::CLOCK_MONOTONIC), Duration::ZERO);
+    // Default epoch: 2025-01-01T00:00:00Z
+    assert_eq!(since_epoch(), Duration::from_secs(1_735_689_600));
+
+    sim.client("test", async {
+        sleep(Duration::from_secs(1)).await;
+
+        let realtime = read_clock(libc::CLOCK_REALTIME);
+        let monotonic = read_clock(libc::CLOCK_MONOTONIC);
+        let process_cpu = read_clock(libc::CLOCK_PROCESS_CPUTIME_ID);
+        let thread_cpu = read_clock(libc::CLOCK_THREAD_CPUTIME_ID);
+
+        assert!(monotonic >= Duration::from_secs(1), "{monotonic:?}");
+        assert!(monotonic < Duration::from_secs(2), "{monotonic:?}");
+        assert_eq!(realtime, Duration::from_secs(1_735_689_600) + monotonic);
+        assert!(process_cpu <= monotonic, "{process_cpu:?}");
+        assert!(thread_cpu <= monotonic, "{thread_cpu:?}");
+        Ok(())
+    });
+    sim.run().unwrap();
+}
+
+#[snowglobe::scene]
+fn invalid_clock_id(_sim: Sim) {
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    let ret = unsafe { libc::clock_gettime(-1, &mut ts) };
+    assert_eq!(ret, -1);
+    assert_eq!(
+        std::io::Error::last_os_error().raw_os_error(),
+        Some(libc::EINVAL)
+    );
+}
+
+#[snowglobe::scene(epoch = "2024-02-29T12:00:00Z")]
+fn scene_epoch(_sim: Sim) {
+    assert_eq!(since_epoch(), Duration::from_secs(1_709_208_000));
+    assert_eq!(read_clock(libc::CLOCK_MONOTONIC), Duration::ZERO);
+}
+
+#[snowglobe::scene(epoch = "2024-02-29T12:00:00Z")]
+fn print_epoch(_sim: Sim) {
+    print!("{}", since_epoch().as_secs());
+}
+
+/// Assert that two durations are within 10ms of each other.
+fn assert_close(a: Duration, b: Duration) {
+    assert!(a.abs_diff(b) < Duration::from_millis(10), "{a:?} != {b:?}");
+}
+
+#[snowglobe::scene]
+fn skew(mut sim: Sim) {
+    sim.client("ahead", async {
+        sleep(Duration::from_secs(1)).await;
+        let reference = turmoil::since_epoch().unwrap();
+        assert_close(since_epoch(), reference + Duration::from_secs(10));
+        Ok(())
+    });
+    sim.client("behind", async {
+        sleep(Duration::from_secs(1)).await;
+        let reference = turmoil::since_epoch().unwrap();
+        assert_close(since_epoch() + Duration::from_secs(5), reference);
+        Ok(())
+    });
+    sim.client("reference", async {
+        sleep(Duration::from_secs(1)).await;
+        let reference = turmoil::since_epoch().unwrap();
+        assert_close(since_epoch(), reference);
+        Ok(())
+    });
+
+    sim.set_clock("ahead", Clock::ahead(Duration::from_secs(10)));
+    sim.set_clock("behind", Clock::behind(Duration::from_secs(5)));
+    sim.run().unwrap();
+}
+
+#[snowglobe::scene]
+fn drift(mut sim: Sim) {
+    sim.client("fast", async {
+        sleep(Duration::from_secs(10)).await;
+        let elapsed = turmoil::sim_elapsed().unwrap();
+        assert_close(read_clock(libc::CLOCK_MONOTONIC), elapsed.mul_f64(1.1));
+        Ok(())
+    });
+    sim.client("slow", async {
+        sleep(Duration::from_secs(10)).await;
+        let elapsed = turmoil::sim_elapsed().unwrap();
+        assert_close(read_clock(libc::CLOCK_MONOTONIC), elapsed.mul_f64(0.9));
+        Ok(())
+    });
+
+    sim.set_clock("fast", Clock::default().drift(0.1));
+    sim.set_clock("slow", Clock::default().drift(-0.1));
+    sim.run().unwrap();
+}
+
+#[snowglobe::scene]
+fn invalid_random_drift(mut sim: Sim) {
+    sim.client("test", async { Ok(()) });
+    sim.set_clock("test", Clock::random(Duration::ZERO, 1.));
+    sim.run().unwrap();
+}
+
+#[snowglobe::scene]
+fn clock_jump(mut sim: Sim) {
+    sim.client("test", async {
+        let mut realtime = since_epoch();
+        let mut monotonic = read_clock(libc::CLOCK_MONOTONIC);
+        let mut jumped = false;
+
+        for _ in 0..40 {
+            sleep(Duration::from_millis(100)).await;
+            let new_realtime = since_epoch();
+            let new_monotonic = read_clock(libc::CLOCK_MONOTONIC);
+
+            assert!(new_monotonic > monotonic, "monotonic clock went backward");
+            if new_realtime < realtime {
+                assert!(realtime - new_realtime > Duration::from_secs(59 * 60));
+                jumped = true;
+            }
+
+            realtime = new_realtime;
+            monotonic = new_monotonic;
+        }
+
+        assert!(jumped, "wall clock did not jump");
+        Ok(())
+    });
+
+    sim.run_for(Duration::from_secs(2)).unwrap();
+    sim.set_clock("test", Clock::behind(Duration::from_secs(60 * 60)));
+    sim.run().unwrap();
+}
+
+/// Setting the clock keeps the time the host slept.
+#[snowglobe::scene(sleep = "advance")]
+fn set_after_sleep(mut sim: Sim) {
+    sim.client("test", async {
+        std::thread::sleep(Duration::from_secs(60));
+        sleep(Duration::from_secs(1)).await;
+        let reference = turmoil::since_epoch().unwrap();
+        assert_close(since_epoch(), reference + Duration::from_secs(70));
+        Ok(())
+    });
+
+    sim.run_for(Duration::from_millis(500)).unwrap();
+    sim.set_clock("test", Clock::ahead(Duration::from_secs(10)));
+    sim.run().unwrap();
+}
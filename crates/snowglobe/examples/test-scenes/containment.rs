@@ -1,3 +1,8 @@
+use std::io;
+use std::process::Command;
+use std::ptr;
+use std::time::{Duration, Instant, SystemTime};
+
 use snowglobe::Sim;
 
 #[snowglobe::scene]
@@ -13,3 +18,131 @@ fn tokio_spawn_blocking(mut sim: Sim) {
     });
     sim.run().unwrap();
 }
+
+#[snowglobe::scene(threads = "green")]
+fn tokio_block_in_place(mut sim: Sim) {
+    sim.client("test", async {
+        tokio::task::block_in_place(|| {});
+        Ok(())
+    });
+    sim.run().unwrap();
+}
+
+#[snowglobe::scene]
+fn thread_sleep(mut sim: Sim) {
+    sim.client("test", async {
+        std::thread::sleep(Duration::from_secs(1));
+        Ok(())
+    });
+    sim.run().unwrap();
+}
+
+#[snowglobe::scene]
+fn libc_usleep(mut sim: Sim) {
+    sim.client("test", async {
+        unsafe { libc::usleep(1000) };
+        Ok(())
+    });
+    sim.run().unwrap();
+}
+
+#[snowglobe::scene(sleep = "advance")]
+fn sleep_outside_host(_sim: Sim) {
+    std::thread::sleep(Duration::from_secs(1));
+}
+
+#[snowglobe::scene(sleep = "advance")]
+fn sleep_advance(mut sim: Sim) {
+    sim.client("test", async {
+        let instant = Instant::now();
+        let system_time = SystemTime::now();
+
+        let ret = unsafe { libc::nanosleep(ptr::null(), ptr::null_mut()) };
+        assert_eq!(ret, -1);
+        assert_eq!(
+            io::Error::last_os_error().raw_os_error(),
+            Some(libc::EFAULT)
+        );
+
+        std::thread::sleep(Duration::from_secs(10));
+        unsafe { libc::sleep(10) };
+        unsafe { libc::usleep(10_000_000) };
+
+        assert_eq!(instant.elapsed(), Duration::from_secs(30));
+        assert_eq!(system_time.elapsed()?, Duration::from_secs(30));
+        Ok(())
+    });
+    sim.run().unwrap();
+}
+
+/// Fail the scene like a program would if it couldn't start a subprocess.
+fn check(ret: libc::c_int) {
+    assert!(ret >= 0, "{}", io::Error::last_os_error());
+    if ret == 0 {
+        // Never reached: this would be the child.
+        unsafe { libc::_exit(0) };
+    }
+}
+
+#[snowglobe::scene]
+fn fork(_sim: Sim) {
+    check(unsafe { libc::fork() });
+}
+
+#[snowglobe::scene]
+fn execve(_sim: Sim) {
+    let argv = [c"true".as_ptr(), ptr::null()];
+    let envp = [ptr::null()];
+    unsafe { libc::execve(c"/bin/true".as_ptr(), argv.as_ptr(), envp.as_ptr()) };
+    panic!("{}", io::Error::last_os_error());
+}
+
+#[snowglobe::scene]
+fn system(_sim: Sim) {
+    let status = unsafe { libc::system(c"true".as_ptr()) };
+    assert_eq!(status, 0, "{}", io::Error::last_os_error());
+}
+
+#[snowglobe::scene]
+fn posix_spawn(_sim: Sim) {
+    let mut pid = 0;
+    let argv = [c"true".as_ptr().cast_mut(), ptr::null_mut()];
+    let envp = [ptr::null_mut()];
+    let ret = unsafe {
+        libc::posix_spawn(
+            &mut pid,
+            c"/bin/true".as_ptr(),
+            ptr::null(),
+            ptr::null(),
+            argv.as_ptr(),
+            envp.as_ptr(),
+        )
+    };
+    assert_eq!(ret, 0, "{}", io::Error::from_raw_os_error(ret));
+}
+
+#[snowglobe::scene]
+fn command(mut sim: Sim) {
+    sim.client("test", async {
+        Command::new("true").status()?;
+        Ok(())
+    });
+    sim.run().unwrap();
+}
+
+#[cfg(target_os = "linux")]
+#[snowglobe::scene]
+fn raw_getrandom(mut sim: Sim) {
+    sim.client("test", async {
+        let mut buf = [0u8; 16];
+        let n = unsafe { libc::syscall(libc::SYS_getrandom, buf.as_mut_ptr(), buf.len(), 0) };
+        assert_eq!(
+            n,
+            buf.len() as libc::c_long,
+            "{}",
+            io::Error::last_os_error()
+        );
+        Ok(())
+    });
+    sim.run().unwrap();
+}
@@ -1,7 +1,9 @@
 use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use std::thread;
 use std::time::Duration;
 
-use snowglobe::Sim;
+use snowglobe::{Clock, Nemesis, Sim};
 use tokio::time::sleep;
 
 #[snowglobe::scene]
@@ -75,6 +77,271 @@ fn std_time(mut sim: Sim) {
     sim.run().unwrap();
 }
 
+/// Run a client that prints the output of `read` at a few simulated times.
+fn print_clock<F>(mut sim: Sim, read: F)
+where
+    F: Fn() -> String + 'static,
+{
+    sim.client("test", async move {
+        print!("{},", read());
+        sleep(Duration::from_secs(1)).await;
+        print!("{},", read());
+        sleep(Duration::from_millis(1)).await;
+        print!("{},", read());
+        sleep(Duration::from_nanos(1)).await;
+        print!("{},", read());
+        Ok(())
+    });
+    sim.run().unwrap();
+}
+
+/// `struct timeb` from `<sys/timeb.h>`.
+#[repr(C)]
+#[derive(Debug, Default)]
+struct Timeb {
+    time: libc::time_t,
+    millitm: libc::c_ushort,
+    timezone: libc::c_short,
+    dstflag: libc::c_short,
+}
+
+// Time functions not exposed by the `libc` crate.
+unsafe extern "C" {
+    fn clock() -> libc::clock_t;
+    fn timespec_get(ts: *mut libc::timespec, base: libc::c_int) -> libc::c_int;
+    fn ftime(tp: *mut Timeb) -> libc::c_int;
+}
+
+#[snowglobe::scene]
+fn gettimeofday(sim: Sim) {
+    print_clock(sim, || {
+        let mut tv = libc::timeval {
+            tv_sec: 0,
+            tv_usec: 0,
+        };
+        unsafe { libc::gettimeofday(&mut tv, std::ptr::null_mut()) };
+        format!("{}.{:06}", tv.tv_sec, tv.tv_usec)
+    });
+}
+
+#[snowglobe::scene]
+fn time(sim: Sim) {
+    print_clock(sim, || {
+        let mut t = 0;
+        let ret = unsafe { libc::time(&mut t) };
+        format!("{ret} {t}")
+    });
+}
+
+#[snowglobe::scene]
+fn clock_ticks(sim: Sim) {
+    print_clock(sim, || unsafe { clock() }.to_string());
+}
+
+#[snowglobe::scene]
+fn timespec_get_utc(sim: Sim) {
+    print_clock(sim, || {
+        let mut ts = libc::timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        let ret = unsafe { timespec_get(&mut ts, 1) };
+        format!("{ret} {}.{:09}", ts.tv_sec, ts.tv_nsec)
+    });
+}
+
+#[snowglobe::scene]
+fn ftime_timeb(sim: Sim) {
+    print_clock(sim, || {
+        let mut tb = Timeb::default();
+        unsafe { ftime(&mut tb) };
+        format!("{tb:?}")
+    });
+}
+
+#[snowglobe::scene]
+fn clock_getres(sim: Sim) {
+    print_clock(sim, || {
+        let mut ts = libc::timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        let ret = unsafe { libc::clock_getres(libc::CLOCK_MONOTONIC, &mut ts) };
+        format!("{ret} {}.{:09}", ts.tv_sec, ts.tv_nsec)
+    });
+}
+
+#[snowglobe::scene]
+fn clock_skew(mut sim: Sim) {
+    use std::time::SystemTime;
+
+    let hosts = ["n1", "n2", "n3"];
+    for host in hosts {
+        sim.client(host, async {
+            for _ in 0..3 {
+                sleep(Duration::from_secs(1)).await;
+                print!("{:?},", SystemTime::now());
+            }
+            Ok(())
+        });
+    }
+    for host in hosts {
+        sim.set_clock(host, Clock::random(Duration::from_secs(60), 1e-3));
+    }
+    sim.run().unwrap();
+}
+
+#[snowglobe::scene]
+fn fs_metadata(mut sim: Sim) {
+    use std::fs;
+
+    sim.client("test", async {
+        fs::create_dir("/dir")?;
+        for name in ["c", "a", "b"] {
+            sleep(Duration::from_millis(10)).await;
+            fs::write(format!("/dir/{name}"), name)?;
+        }
+        for entry in fs::read_dir("/dir")? {
+            let entry = entry?;
+            let meta = entry.metadata()?;
+            print!(
+                "{:?} {:?} {},",
+                entry.file_name(),
+                meta.modified()?,
+                meta.len()
+            );
+        }
+        Ok(())
+    });
+    sim.run().unwrap();
+}
+
+#[snowglobe::scene]
+fn fs_crash(mut sim: Sim) {
+    use std::cell::Cell;
+    use std::fs;
+    use std::rc::Rc;
+
+    let runs = Rc::new(Cell::new(0));
+
+    let host_runs = runs.clone();
+    sim.host("writer", move || {
+        let runs = host_runs.clone();
+        async move {
+            runs.set(runs.get() + 1);
+            for i in 0..10 {
+                let path = format!("/file-{i}");
+                match runs.get() {
+                    1 => fs::write(path, b"unsynced data")?,
+                    _ => print!("{:?},", fs::read(path).ok()),
+                }
+            }
+            std::future::pending().await
+        }
+    });
+
+    sim.run_for(Duration::from_secs(1)).unwrap();
+    sim.crash("writer");
+    sim.bounce("writer");
+    sim.run_for(Duration::from_secs(1)).unwrap();
+}
+
+#[snowglobe::scene]
+fn dev_urandom(mut sim: Sim) {
+    use std::fs::File;
+    use std::io::Read;
+
+    // Opened outside of any host, then used from within one.
+    let mut urandom = File::open("/dev/urandom").unwrap();
+    let mut buf = [0; 16];
+    urandom.read_exact(&mut buf).unwrap();
+    print!("{buf:?},");
+
+    sim.client("test", async move {
+        urandom.read_exact(&mut buf)?;
+        print!("{buf:?},");
+        File::open("/dev/random")?.read_exact(&mut buf)?;
+        print!("{buf:?}");
+        Ok(())
+    });
+    sim.run().unwrap();
+}
+
+unsafe extern "C" {
+    fn rand() -> libc::c_int;
+    fn srand(seed: libc::c_uint);
+    fn rand_r(seedp: *mut libc::c_uint) -> libc::c_int;
+    fn random() -> libc::c_long;
+    fn drand48() -> libc::c_double;
+    fn lrand48() -> libc::c_long;
+    fn mrand48() -> libc::c_long;
+    fn erand48(xsubi: *mut libc::c_ushort) -> libc::c_double;
+    fn srand48(seedval: libc::c_long);
+    fn arc4random() -> u32;
+    fn arc4random_buf(buf: *mut libc::c_void, nbytes: libc::size_t);
+    fn arc4random_uniform(upper_bound: u32) -> u32;
+}
+
+#[snowglobe::scene]
+fn libc_rand(_sim: Sim) {
+    unsafe {
+        print!("{},{},", rand(), random());
+        print!("{},{},{},", drand48(), lrand48(), mrand48());
+        let mut xsubi = [1, 2, 3];
+        print!("{},{xsubi:?},", erand48(xsubi.as_mut_ptr()));
+        print!("{},{},", arc4random(), arc4random_uniform(100));
+        let mut buf = [0u8; 8];
+        arc4random_buf(buf.as_mut_ptr().cast(), buf.len());
+        print!("{buf:?},");
+
+        // Reseeding reproduces the same sequence.
+        srand(42);
+        let first = [rand(), rand()];
+        srand(42);
+        assert_eq!(first, [rand(), rand()]);
+        srand48(42);
+        let first = drand48();
+        srand48(42);
+        assert_eq!(first, drand48());
+
+        let mut seed = 42;
+        print!("{},{}", rand_r(&mut seed), rand_r(&mut seed));
+    }
+}
+
+#[snowglobe::scene(threads = "green")]
+fn green_threads(_sim: Sim) {
+    let order = Arc::new(Mutex::new(Vec::new()));
+    let handles: Vec<_> = (0..4)
+        .map(|i| {
+            let order = order.clone();
+            thread::spawn(move || {
+                for _ in 0..3 {
+                    order.lock().unwrap().push(i);
+                    thread::yield_now();
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    print!("{:?}", order.lock().unwrap());
+}
+
+#[snowglobe::scene(threads = "green")]
+fn spawn_blocking(mut sim: Sim) {
+    for client in ["a", "b", "c"] {
+        sim.client(client, async move {
+            for i in 0..3 {
+                tokio::task::spawn_blocking(move || print!("{client}{i},")).await?;
+            }
+            Ok(())
+        });
+    }
+    sim.run().unwrap();
+}
+
 #[snowglobe::scene]
 fn uuid(_sim: Sim) {
     print!("{}", uuid::Uuid::now_v7());
@@ -91,3 +358,53 @@ fn heap_address_ffi(_sim: Sim) {
     let ptr = unsafe { libc::malloc(1) };
     print!("{:?}", ptr);
 }
+
+#[snowglobe::scene]
+fn heap_address_realloc(_sim: Sim) {
+    // Reallocating a null pointer allocates.
+    let ptr = unsafe { libc::realloc(std::ptr::null_mut(), 16) };
+    assert!(!ptr.is_null());
+    unsafe { ptr.write_bytes(1, 16) };
+    let ptr = unsafe { libc::realloc(ptr, 64) };
+    assert_eq!(unsafe { *ptr.cast::<u8>().add(15) }, 1);
+    print!("{:?}", ptr);
+}
+
+#[snowglobe::scene]
+fn buggify(_sim: Sim) {
+    for _ in 0..100 {
+        let a = snowglobe::buggify!();
+        let b = snowglobe::buggify!(0.5);
+        let c = snowglobe::buggify!(1.0);
+        // Out of range probabilities are clamped.
+        let d = snowglobe::buggify!(1.5);
+        let e = snowglobe::buggify!(-1.0);
+        let f = snowglobe::buggify!(f64::NAN);
+        assert!(!e && !f);
+        print!("{}{}{}{},", a as u8, b as u8, c as u8, d as u8);
+    }
+}
+
+#[snowglobe::scene]
+fn nemesis(mut sim: Sim) {
+    let hosts = ["n1", "n2", "n3"];
+    for host in hosts {
+        sim.host(host, std::future::pending);
+    }
+    sim.client("client", async {
+        sleep(Duration::from_secs(5)).await;
+        Ok(())
+    });
+
+    sim.nemesis(
+        Nemesis::builder()
+            .hosts(hosts)
+            .interval(Duration::from_millis(10), Duration::from_millis(100))
+            .build(),
+    );
+    while !sim.step().unwrap() {
+        for host in hosts {
+            print!("{}", sim.is_host_running(host) as u8);
+        }
+    }
+}
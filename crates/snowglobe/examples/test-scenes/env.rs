@@ -0,0 +1,128 @@
+use std::cell::Cell;
+use std::env;
+use std::ffi::CStr;
+use std::io;
+use std::rc::Rc;
+use std::time::Duration;
+
+use snowglobe::Sim;
+
+#[cfg(target_os = "linux")]
+unsafe extern "C" {
+    fn secure_getenv(name: *const libc::c_char) -> *mut libc::c_char;
+}
+
+fn vars() -> Vec<(String, String)> {
+    let mut vars: Vec<_> = env::vars().collect();
+    vars.sort();
+    vars
+}
+
+fn pairs(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+    vars.iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect()
+}
+
+#[snowglobe::scene(env = ["GREETING=hello", "HOME=/home/sim"])]
+fn scene_env(mut sim: Sim) {
+    let expected = pairs(&[("GREETING", "hello"), ("HOME", "/home/sim")]);
+    // The process was started by cargo, which sets plenty of variables.
+    assert_eq!(vars(), expected);
+    assert!(env::var_os("CARGO").is_none());
+
+    sim.client("test", async move {
+        assert_eq!(vars(), expected);
+        let greeting = unsafe { libc::getenv(c"GREETING".as_ptr()) };
+        assert_eq!(unsafe { CStr::from_ptr(greeting) }, c"hello");
+        #[cfg(target_os = "linux")]
+        {
+            let home = unsafe { secure_getenv(c"HOME".as_ptr()) };
+            assert_eq!(unsafe { CStr::from_ptr(home) }, c"/home/sim");
+        }
+        Ok(())
+    });
+    sim.run().unwrap();
+}
+
+#[snowglobe::scene]
+fn empty_by_default(_sim: Sim) {
+    assert_eq!(vars(), []);
+}
+
+#[snowglobe::scene(env = ["GREETING=hello"])]
+fn host_env(mut sim: Sim) {
+    sim.client("a", async {
+        assert_eq!(vars(), pairs(&[("GREETING", "hello"), ("ROLE", "leader")]));
+        Ok(())
+    });
+    sim.client("b", async {
+        assert_eq!(vars(), []);
+        Ok(())
+    });
+    sim.set_env("a", "ROLE", "leader");
+    sim.remove_env("b", "GREETING");
+    sim.run().unwrap();
+
+    assert_eq!(vars(), pairs(&[("GREETING", "hello")]));
+}
+
+#[snowglobe::scene]
+fn process_env(mut sim: Sim) {
+    let runs = Rc::new(Cell::new(0));
+
+    let host_runs = runs.clone();
+    sim.host("server", move || {
+        let runs = host_runs.clone();
+        async move {
+            runs.set(runs.get() + 1);
+            // Variables set by the process survive across yields, but not restarts.
+            assert_eq!(env::var_os("STARTED"), None);
+            unsafe { env::set_var("STARTED", "1") };
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            assert_eq!(env::var("STARTED")?, "1");
+            std::future::pending().await
+        }
+    });
+    sim.client("other", async {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(env::var_os("STARTED"), None);
+        unsafe { env::set_var("OTHER", "1") };
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(vars(), [("OTHER".into(), "1".into())]);
+        unsafe { env::remove_var("OTHER") };
+        assert_eq!(vars(), []);
+        Ok(())
+    });
+
+    sim.run_for(Duration::from_secs(1)).unwrap();
+    assert_eq!(env::var_os("STARTED"), None);
+    sim.bounce("server");
+    sim.run_for(Duration::from_secs(1)).unwrap();
+    assert_eq!(runs.get(), 2);
+}
+
+#[snowglobe::scene]
+fn libc_setenv(_sim: Sim) {
+    let ret = unsafe { libc::setenv(c"KEY".as_ptr(), std::ptr::null(), 1) };
+    assert_eq!(ret, -1);
+    assert_eq!(
+        io::Error::last_os_error().raw_os_error(),
+        Some(libc::EINVAL)
+    );
+
+    for value in [c"a", c"b"] {
+        assert_eq!(
+            unsafe { libc::setenv(c"KEY".as_ptr(), value.as_ptr(), 0) },
+            0
+        );
+    }
+    assert_eq!(vars(), pairs(&[("KEY", "a")]));
+    for _ in 0..1000 {
+        assert_eq!(
+            unsafe { libc::setenv(c"KEY".as_ptr(), c"c".as_ptr(), 1) },
+            0
+        );
+    }
+    assert_eq!(vars(), pairs(&[("KEY", "c")]));
+}
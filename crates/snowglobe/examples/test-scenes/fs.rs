@@ -0,0 +1,378 @@
+use std::cell::Cell;
+use std::env;
+use std::ffi::CStr;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::{FileExt, OpenOptionsExt};
+use std::path::Path;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use snowglobe::Sim;
+use tokio::time::sleep;
+
+#[snowglobe::scene]
+fn file_roundtrip(mut sim: Sim) {
+    sim.client("test", async {
+        fs::create_dir_all("/data/db")?;
+        fs::write("/data/db/a", b"hello")?;
+        assert_eq!(fs::read("/data/db/a")?, b"hello");
+        assert_eq!(fs::metadata("/data/db/a")?.len(), 5);
+        assert!(fs::metadata("/data/db")?.is_dir());
+
+        let mut file = OpenOptions::new().append(true).open("/data/db/a")?;
+        file.write_all(b" world")?;
+        drop(file);
+        assert_eq!(fs::read_to_string("/data/db/a")?, "hello world");
+
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open("/data/db/a")?;
+        file.write_all_at(b"J", 0)?;
+        file.seek(SeekFrom::Start(6))?;
+        let mut rest = String::new();
+        file.read_to_string(&mut rest)?;
+        assert_eq!(rest, "world");
+        file.set_len(5)?;
+        assert_eq!(file.metadata()?.len(), 5);
+        drop(file);
+        assert_eq!(fs::read("/data/db/a")?, b"Jello");
+
+        fs::rename("/data/db/a", "/data/db/b")?;
+        fs::write("/data/db/c", b"")?;
+        let mut names: Vec<_> = fs::read_dir("/data/db")?
+            .map(|e| e.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["b", "c"]);
+
+        fs::remove_file("/data/db/b")?;
+        let err = fs::read("/data/db/b").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+        let err = fs::remove_dir("/data/db").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::DirectoryNotEmpty);
+        fs::remove_dir_all("/data")?;
+        assert!(!Path::new("/data").exists());
+        Ok(())
+    });
+    sim.run().unwrap();
+}
+
+#[snowglobe::scene]
+fn host_isolation(mut sim: Sim) {
+    sim.client("a", async {
+        fs::write("/shared", b"a")?;
+        sleep(Duration::from_secs(1)).await;
+        assert_eq!(fs::read("/shared")?, b"a");
+        Ok(())
+    });
+    sim.client("b", async {
+        sleep(Duration::from_millis(500)).await;
+        let err = fs::read("/shared").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+        Ok(())
+    });
+    sim.run().unwrap();
+}
+
+#[snowglobe::scene]
+fn no_real_disk(mut sim: Sim) {
+    let path = std::env::temp_dir().join("snowglobe-vfs-test");
+    let _ = fs::remove_file(&path);
+
+    let host_path = path.clone();
+    sim.client("test", async move {
+        fs::create_dir_all(host_path.parent().unwrap())?;
+        File::create(&host_path)?.write_all(b"simulated")?;
+        assert!(host_path.exists());
+        Ok(())
+    });
+    sim.run().unwrap();
+
+    assert!(!path.exists(), "simulated write reached the real disk");
+}
+
+#[snowglobe::scene]
+fn survives_restart(mut sim: Sim) {
+    let runs = Rc::new(Cell::new(0));
+
+    let host_runs = runs.clone();
+    sim.host("server", move || {
+        let runs = host_runs.clone();
+        async move {
+            runs.set(runs.get() + 1);
+            if runs.get() == 1 {
+                fs::write("/state", b"persisted")?;
+            } else {
+                assert_eq!(fs::read("/state")?, b"persisted");
+            }
+            std::future::pending().await
+        }
+    });
+
+    sim.run_for(Duration::from_secs(1)).unwrap();
+    sim.bounce("server");
+    sim.run_for(Duration::from_secs(1)).unwrap();
+    assert_eq!(runs.get(), 2);
+}
+
+#[snowglobe::scene]
+fn synced_survives_crash(mut sim: Sim) {
+    let runs = Rc::new(Cell::new(0));
+
+    let host_runs = runs.clone();
+    sim.host("server", move || {
+        let runs = host_runs.clone();
+        async move {
+            runs.set(runs.get() + 1);
+            if runs.get() == 1 {
+                fs::create_dir("/data")?;
+                File::open("/")?.sync_all()?;
+                let mut file = File::create("/data/state")?;
+                file.write_all(b"durable")?;
+                file.sync_all()?;
+                File::open("/data")?.sync_all()?;
+
+                let mut sync = OpenOptions::new()
+                    .write(true)
+                    .create(true)
+                    .custom_flags(libc::O_SYNC)
+                    .open("/data/log")?;
+                sync.write_all(b"entry")?;
+                File::open("/data")?.sync_all()?;
+            } else {
+                assert_eq!(fs::read("/data/state")?, b"durable");
+                assert_eq!(fs::read("/data/log")?, b"entry");
+            }
+            std::future::pending().await
+        }
+    });
+
+    sim.run_for(Duration::from_secs(1)).unwrap();
+    sim.crash("server");
+    sim.bounce("server");
+    sim.run_for(Duration::from_secs(1)).unwrap();
+    assert_eq!(runs.get(), 2);
+}
+
+#[snowglobe::scene]
+fn unsynced_lost_on_crash(mut sim: Sim) {
+    const FILES: usize = 20;
+    const CONTENT: &[u8] = b"written but never synced";
+
+    let runs = Rc::new(Cell::new(0));
+
+    let host_runs = runs.clone();
+    sim.host("server", move || {
+        let runs = host_runs.clone();
+        async move {
+            runs.set(runs.get() + 1);
+            if runs.get() == 1 {
+                // Atomically replace a synced file with a new version, without syncing the
+                // directory afterwards.
+                fs::write("/config", b"old")?;
+                File::open("/config")?.sync_all()?;
+                File::open("/")?.sync_all()?;
+                let mut tmp = File::create("/config.tmp")?;
+                tmp.write_all(b"new")?;
+                tmp.sync_all()?;
+                fs::rename("/config.tmp", "/config")?;
+
+                for i in 0..FILES {
+                    fs::write(format!("/file-{i}"), CONTENT)?;
+                }
+            } else {
+                let config = fs::read("/config")?;
+                assert!(config == b"old" || config == b"new", "{config:?}");
+
+                let mut intact = 0;
+                for i in 0..FILES {
+                    match fs::read(format!("/file-{i}")) {
+                        Ok(data) => {
+                            assert!(CONTENT.starts_with(&data), "{data:?}");
+                            intact += usize::from(data == CONTENT);
+                        }
+                        Err(err) => assert_eq!(err.kind(), ErrorKind::NotFound),
+                    }
+                }
+                assert!(intact < FILES, "no unsynced write was lost");
+            }
+            std::future::pending().await
+        }
+    });
+
+    sim.run_for(Duration::from_secs(1)).unwrap();
+    sim.crash("server");
+    sim.bounce("server");
+    sim.run_for(Duration::from_secs(1)).unwrap();
+    assert_eq!(runs.get(), 2);
+}
+
+#[snowglobe::scene]
+fn dir_used_outside_host(mut sim: Sim) {
+    static DIR: AtomicUsize = AtomicUsize::new(0);
+
+    sim.host("server", || async {
+        fs::create_dir("/data")?;
+        let dir = unsafe { libc::opendir(c"/".as_ptr()) };
+        assert!(!dir.is_null());
+        DIR.store(dir as usize, Ordering::Relaxed);
+        std::future::pending().await
+    });
+    sim.run_for(Duration::from_secs(1)).unwrap();
+
+    let dir = DIR.load(Ordering::Relaxed) as *mut libc::DIR;
+    let mut names = Vec::new();
+    loop {
+        let entry = unsafe { libc::readdir(dir) };
+        if entry.is_null() {
+            break;
+        }
+        let name = unsafe { CStr::from_ptr((*entry).d_name.as_ptr()) };
+        names.push(name.to_str().unwrap().to_owned());
+    }
+    assert_eq!(names, [".", "..", "data"]);
+    assert_eq!(unsafe { libc::closedir(dir) }, 0);
+}
+
+#[snowglobe::scene]
+fn working_directory(mut sim: Sim) {
+    sim.client("test", async {
+        assert_eq!(env::current_dir()?, Path::new("/"));
+        fs::create_dir_all("/data/db")?;
+        env::set_current_dir("/data")?;
+        assert_eq!(env::current_dir()?, Path::new("/data"));
+
+        fs::write("db/file", b"hello")?;
+        assert_eq!(fs::read("/data/db/file")?, b"hello");
+        let err = env::set_current_dir("db/file").unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::ENOTDIR));
+
+        fs::create_dir("/gone")?;
+        env::set_current_dir("/gone")?;
+        fs::remove_dir("/gone")?;
+        let err = env::current_dir().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+        Ok(())
+    });
+    sim.run().unwrap();
+}
+
+#[snowglobe::scene]
+fn paths(mut sim: Sim) {
+    sim.client("test", async {
+        fs::create_dir_all("/data/db")?;
+        fs::write("/data/db/file", b"")?;
+        env::set_current_dir("/data")?;
+
+        assert_eq!(
+            fs::canonicalize("db/../db/./file")?,
+            Path::new("/data/db/file")
+        );
+        assert_eq!(fs::canonicalize("..")?, Path::new("/"));
+        let err = fs::canonicalize("missing").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+
+        let err = fs::read_link("db/file").unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::EINVAL));
+
+        let path = c"/data/db/file".as_ptr();
+        assert_eq!(unsafe { libc::access(path, libc::R_OK | libc::W_OK) }, 0);
+        assert_eq!(unsafe { libc::access(path, libc::X_OK) }, -1);
+        let err = io::Error::last_os_error();
+        assert_eq!(err.raw_os_error(), Some(libc::EACCES));
+
+        // System files are not visible to hosts.
+        let err = fs::metadata("/etc/hosts").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+        Ok(())
+    });
+    sim.run().unwrap();
+}
+
+#[snowglobe::scene]
+fn dup(mut sim: Sim) {
+    sim.client("test", async {
+        let mut file = File::create("/file")?;
+        let mut clone = file.try_clone()?;
+        file.write_all(b"hello ")?;
+        clone.write_all(b"world")?;
+        drop(file);
+        assert_eq!(fs::read("/file")?, b"hello world");
+
+        let other = File::open("/file")?;
+        let fd = unsafe { libc::dup2(other.as_raw_fd(), clone.as_raw_fd()) };
+        assert_eq!(fd, clone.as_raw_fd());
+        let mut data = String::new();
+        clone.read_to_string(&mut data)?;
+        assert_eq!(data, "hello world");
+
+        // Virtual files can't replace real file descriptors.
+        assert_eq!(unsafe { libc::dup2(other.as_raw_fd(), 1) }, -1);
+        Ok(())
+    });
+    sim.run().unwrap();
+}
+
+#[snowglobe::scene(disk_enospc_rate = 1.0)]
+fn disk_full(mut sim: Sim) {
+    sim.client("test", async {
+        let err = fs::write("/file", b"data").unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::ENOSPC));
+        assert_eq!(fs::read("/file")?, b"");
+        Ok(())
+    });
+    sim.run().unwrap();
+}
+
+#[snowglobe::scene]
+fn disk_errors_per_host(mut sim: Sim) {
+    sim.client("faulty", async {
+        let err = fs::write("/file", b"data").unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::EIO));
+        Ok(())
+    });
+    sim.client("healthy", async {
+        fs::write("/file", b"data")?;
+        assert_eq!(fs::read("/file")?, b"data");
+        Ok(())
+    });
+    sim.disk("faulty").eio_rate(1.0);
+    sim.run().unwrap();
+}
+
+#[snowglobe::scene(disk_short_io_rate = 1.0)]
+fn short_io(mut sim: Sim) {
+    sim.client("test", async {
+        let mut file = File::create("/file")?;
+        let n = file.write(b"hello world")?;
+        assert!(n < 11, "{n}");
+        file.write_all(&b"hello world"[n..])?;
+        drop(file);
+
+        let mut file = File::open("/file")?;
+        let mut buf = [0; 11];
+        let n = file.read(&mut buf)?;
+        assert!(n < 11, "{n}");
+        file.read_exact(&mut buf[n..])?;
+        assert_eq!(&buf, b"hello world");
+        Ok(())
+    });
+    sim.run().unwrap();
+}
+
+#[snowglobe::scene(disk_latency = "10ms")]
+fn disk_latency(mut sim: Sim) {
+    sim.client("test", async {
+        let start = Instant::now();
+        let mut file = File::create("/file")?;
+        file.write_all(b"data")?;
+        file.sync_all()?;
+        assert_eq!(start.elapsed(), Duration::from_millis(20));
+        Ok(())
+    });
+    sim.run().unwrap();
+}
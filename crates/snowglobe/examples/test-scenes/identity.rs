@@ -0,0 +1,160 @@
+use std::ffi::CStr;
+use std::thread;
+use std::{io, process, ptr};
+
+use snowglobe::Sim;
+
+fn hostname() -> String {
+    let mut buf = [0; 64];
+    let ret = unsafe { libc::gethostname(buf.as_mut_ptr(), buf.len()) };
+    assert_eq!(ret, 0);
+    unsafe { CStr::from_ptr(buf.as_ptr()) }
+        .to_str()
+        .unwrap()
+        .into()
+}
+
+fn nodename() -> String {
+    let mut buf = unsafe { std::mem::zeroed::<libc::utsname>() };
+    assert_eq!(unsafe { libc::uname(&mut buf) }, 0);
+    unsafe { CStr::from_ptr(buf.nodename.as_ptr()) }
+        .to_str()
+        .unwrap()
+        .into()
+}
+
+fn cpus() -> usize {
+    thread::available_parallelism().unwrap().get()
+}
+
+#[snowglobe::scene]
+fn pids(mut sim: Sim) {
+    assert_eq!(process::id(), 12345);
+
+    sim.host("server", || async {
+        assert_eq!(process::id(), 12346);
+        #[cfg(target_os = "linux")]
+        assert_eq!(unsafe { libc::gettid() }, 12346);
+        assert_eq!(unsafe { libc::getppid() }, 1);
+        Ok(())
+    });
+    sim.client("client", async {
+        assert_eq!(process::id(), 12347);
+        Ok(())
+    });
+    sim.run().unwrap();
+}
+
+#[snowglobe::scene]
+fn pid_survives_restart(mut sim: Sim) {
+    sim.host("server", || async {
+        assert_eq!(process::id(), 12346);
+        std::future::pending().await
+    });
+    sim.client("client", async { Ok(()) });
+    sim.step().unwrap();
+    sim.bounce("server");
+    sim.run().unwrap();
+}
+
+#[snowglobe::scene]
+fn hostnames(mut sim: Sim) {
+    assert_eq!(hostname(), "snowglobe");
+    assert_eq!(nodename(), "snowglobe");
+
+    sim.client("client", async {
+        assert_eq!(hostname(), "client");
+        assert_eq!(nodename(), "client");
+
+        let mut buf = [0; 4];
+        let ret = unsafe { libc::gethostname(buf.as_mut_ptr(), buf.len()) };
+        assert_eq!(ret, -1);
+        assert_eq!(
+            std::io::Error::last_os_error().raw_os_error(),
+            Some(libc::ENAMETOOLONG)
+        );
+        Ok(())
+    });
+    sim.run().unwrap();
+}
+
+/// Hosts are tracked regardless of the tracing subscriber the program installs.
+#[snowglobe::scene]
+fn own_subscriber(mut sim: Sim) {
+    let _guard = tracing::subscriber::set_default(tracing::subscriber::NoSubscriber::default());
+
+    sim.client("client", async {
+        assert_eq!(hostname(), "client");
+        Ok(())
+    });
+    sim.run().unwrap();
+}
+
+#[snowglobe::scene]
+fn users(mut sim: Sim) {
+    sim.client("client", async {
+        unsafe {
+            assert_eq!(libc::getuid(), 1000);
+            assert_eq!(libc::geteuid(), 1000);
+            assert_eq!(libc::getgid(), 1000);
+            assert_eq!(libc::getegid(), 1000);
+        }
+        Ok(())
+    });
+    sim.run().unwrap();
+}
+
+#[snowglobe::scene]
+fn default_cpus(mut sim: Sim) {
+    sim.client("client", async {
+        assert_eq!(cpus(), 4);
+        assert_eq!(unsafe { libc::sysconf(libc::_SC_NPROCESSORS_ONLN) }, 4);
+        Ok(())
+    });
+    sim.run().unwrap();
+}
+
+#[snowglobe::scene(cpus = 2)]
+fn scene_cpus(mut sim: Sim) {
+    sim.client("client", async {
+        assert_eq!(cpus(), 2);
+        assert_eq!(unsafe { libc::sysconf(libc::_SC_NPROCESSORS_CONF) }, 2);
+        // Other names still reach libc.
+        assert!(unsafe { libc::sysconf(libc::_SC_PAGESIZE) } > 0);
+        Ok(())
+    });
+    sim.run().unwrap();
+}
+
+fn errno() -> i32 {
+    io::Error::last_os_error().raw_os_error().unwrap()
+}
+
+#[snowglobe::scene]
+fn invalid_arguments(mut sim: Sim) {
+    sim.client("test", async {
+        assert_eq!(unsafe { libc::uname(ptr::null_mut()) }, -1);
+        assert_eq!(errno(), libc::EFAULT);
+
+        #[cfg(target_os = "linux")]
+        {
+            let mut set = unsafe { std::mem::zeroed::<libc::cpu_set_t>() };
+            let size = std::mem::size_of_val(&set);
+            assert_eq!(
+                unsafe { libc::sched_getaffinity(0, size, ptr::null_mut()) },
+                -1
+            );
+            assert_eq!(errno(), libc::EFAULT);
+            // The scene bundle's process is not visible from a host.
+            assert_eq!(
+                unsafe { libc::sched_getaffinity(12345, size, &mut set) },
+                -1
+            );
+            assert_eq!(errno(), libc::ESRCH);
+            let pid = process::id() as libc::pid_t;
+            assert_eq!(unsafe { libc::sched_getaffinity(pid, size, &mut set) }, 0);
+        }
+        Ok(())
+    });
+    sim.run().unwrap();
+}
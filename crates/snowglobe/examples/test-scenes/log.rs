@@ -0,0 +1,14 @@
+use std::time::Duration;
+
+use snowglobe::Sim;
+use tokio::time::sleep;
+
+#[snowglobe::scene]
+fn host_lines(mut sim: Sim) {
+    sim.client("client", async {
+        sleep(Duration::from_millis(1500)).await;
+        tracing::info!("hello from client");
+        Ok(())
+    });
+    sim.run().unwrap();
+}
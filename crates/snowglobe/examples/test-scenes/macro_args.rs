@@ -22,6 +22,16 @@ fn bare(_sim: Sim) {
             max_message_latency: None,
             fail_rate: None,
             repair_rate: None,
+            epoch: None,
+            sleep: None,
+            disk_eio_rate: None,
+            disk_enospc_rate: None,
+            disk_edquot_rate: None,
+            disk_short_io_rate: None,
+            disk_latency: None,
+            env: None,
+            cpus: None,
+            threads: None,
         }
     );
 }
@@ -43,6 +53,16 @@ fn durations(_sim: Sim) {
             max_message_latency: Some(Duration::from_millis(100)),
             fail_rate: None,
             repair_rate: None,
+            epoch: None,
+            sleep: None,
+            disk_eio_rate: None,
+            disk_enospc_rate: None,
+            disk_edquot_rate: None,
+            disk_short_io_rate: None,
+            disk_latency: None,
+            env: None,
+            cpus: None,
+            threads: None,
         }
     );
 }
@@ -59,6 +79,121 @@ fn rates(_sim: Sim) {
             max_message_latency: None,
             fail_rate: Some(0.1),
             repair_rate: Some(0.5),
+            epoch: None,
+            sleep: None,
+            disk_eio_rate: None,
+            disk_enospc_rate: None,
+            disk_edquot_rate: None,
+            disk_short_io_rate: None,
+            disk_latency: None,
+            env: None,
+            cpus: None,
+            threads: None,
         }
     );
 }
+
+#[snowglobe::scene(epoch = "2024-02-29T12:00:00Z")]
+fn epoch(_sim: Sim) {
+    let scene = get_scene("epoch");
+    assert_eq!(
+        scene.config,
+        SceneConfig {
+            simulation_duration: None,
+            tick_duration: None,
+            min_message_latency: None,
+            max_message_latency: None,
+            fail_rate: None,
+            repair_rate: None,
+            epoch: Some(Duration::from_secs(1_709_208_000)),
+            sleep: None,
+            disk_eio_rate: None,
+            disk_enospc_rate: None,
+            disk_edquot_rate: None,
+            disk_short_io_rate: None,
+            disk_latency: None,
+            env: None,
+            cpus: None,
+            threads: None,
+        }
+    );
+}
+
+#[snowglobe::scene(sleep = "advance")]
+fn sleep(_sim: Sim) {
+    let scene = get_scene("sleep");
+    assert_eq!(
+        scene.config,
+        SceneConfig {
+            simulation_duration: None,
+            tick_duration: None,
+            min_message_latency: None,
+            max_message_latency: None,
+            fail_rate: None,
+            repair_rate: None,
+            epoch: None,
+            sleep: Some(SleepMode::Advance),
+            disk_eio_rate: None,
+            disk_enospc_rate: None,
+            disk_edquot_rate: None,
+            disk_short_io_rate: None,
+            disk_latency: None,
+            env: None,
+            cpus: None,
+            threads: None,
+        }
+    );
+}
+
+#[snowglobe::scene(
+    disk_eio_rate = 0.01,
+    disk_enospc_rate = 0.02,
+    disk_edquot_rate = 0.03,
+    disk_short_io_rate = 0.1,
+    disk_latency = "2ms"
+)]
+fn disk(_sim: Sim) {
+    let scene = get_scene("disk");
+    assert_eq!(
+        scene.config,
+        SceneConfig {
+            simulation_duration: None,
+            tick_duration: None,
+            min_message_latency: None,
+            max_message_latency: None,
+            fail_rate: None,
+            repair_rate: None,
+            epoch: None,
+            sleep: None,
+            disk_eio_rate: Some(0.01),
+            disk_enospc_rate: Some(0.02),
+            disk_edquot_rate: Some(0.03),
+            disk_short_io_rate: Some(0.1),
+            disk_latency: Some(Duration::from_millis(2)),
+            env: None,
+            cpus: None,
+            threads: None,
+        }
+    );
+}
+
+#[snowglobe::scene(env = ["HOME=/home/sim", "EMPTY=", "EQUALS=a=b"])]
+fn env(_sim: Sim) {
+    let scene = get_scene("env");
+    assert_eq!(
+        scene.config.env,
+        Some(&[("HOME", "/home/sim"), ("EMPTY", ""), ("EQUALS", "a=b")][..])
+    );
+}
+
+#[snowglobe::scene(cpus = 8)]
+fn cpus(_sim: Sim) {
+    let scene = get_scene("cpus");
+    assert_eq!(scene.config.cpus, Some(8));
+}
+
+#[snowglobe::scene(threads = "green")]
+fn threads(_sim: Sim) {
+    let scene = get_scene("threads");
+    assert_eq!(scene.config.threads, Some(ThreadMode::Green));
+}
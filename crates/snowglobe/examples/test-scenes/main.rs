@@ -1,6 +1,14 @@
+mod clock;
 mod containment;
 mod determinism;
+mod env;
+mod fs;
+mod identity;
+mod log;
 mod macro_args;
+mod property;
+mod sim;
+mod threads;
 
 fn main() -> snowglobe::Result {
     snowglobe::main()
@@ -0,0 +1,20 @@
+use snowglobe::{Sim, always, reachable, sometimes};
+
+#[snowglobe::scene]
+fn coverage(_sim: Sim) {
+    for i in 0..10 {
+        always!(i < 10, "index in bounds");
+        sometimes!(i == 5, "index is five");
+        sometimes!(i > 10, "index out of bounds");
+        if i == 0 {
+            reachable!("first iteration");
+        }
+    }
+}
+
+#[snowglobe::scene]
+fn always_violated(_sim: Sim) {
+    for i in 0..10 {
+        always!(i < 5, "index below five");
+    }
+}
@@ -0,0 +1,351 @@
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::time::Duration;
+
+use snowglobe::{Nemesis, Sim};
+use tokio::time::sleep;
+
+#[snowglobe::scene]
+fn network_faults(mut sim: Sim) {
+    sim.client("a", async { Ok(()) });
+    sim.client("b", async { Ok(()) });
+
+    sim.partition("a", "b");
+    sim.repair("a", "b");
+    sim.partition_oneway("a", "b");
+    sim.repair_oneway("a", "b");
+    sim.hold("a", "b");
+    sim.release("a", "b");
+
+    sim.run().unwrap();
+}
+
+#[snowglobe::scene]
+fn failure_during_partition(mut sim: Sim) {
+    sim.client("a", async { Ok(()) });
+    sim.client("b", async { Err("boom".into()) });
+
+    sim.partition_oneway("a", "b");
+    sim.run().unwrap();
+}
+
+#[snowglobe::scene]
+fn host_lifecycle(mut sim: Sim) {
+    static STARTS: AtomicU32 = AtomicU32::new(0);
+
+    sim.host("server", || async {
+        STARTS.fetch_add(1, Ordering::Relaxed);
+        std::future::pending::<()>().await;
+        Ok(())
+    });
+    sim.client("client", async {
+        sleep(Duration::from_secs(2)).await;
+        Ok(())
+    });
+
+    sim.step().unwrap();
+    assert_eq!(STARTS.load(Ordering::Relaxed), 1);
+
+    sim.crash("server");
+    assert!(!sim.is_host_running("server"));
+    sim.bounce("server");
+    assert!(sim.is_host_running("server"));
+    sim.step().unwrap();
+    assert_eq!(STARTS.load(Ordering::Relaxed), 2);
+
+    // Crashing a host cancels its pending restart.
+    sim.restart_after("server", Duration::from_millis(100));
+    sim.crash("server");
+    sim.run_for(Duration::from_millis(500)).unwrap();
+    assert!(!sim.is_host_running("server"));
+
+    sim.restart_after("server", Duration::from_secs(1));
+    assert!(!sim.is_host_running("server"));
+
+    sim.run().unwrap();
+    assert!(sim.is_host_running("server"));
+    assert_eq!(STARTS.load(Ordering::Relaxed), 3);
+}
+
+#[snowglobe::scene]
+fn restart_after_twice(mut sim: Sim) {
+    static STARTS: AtomicU32 = AtomicU32::new(0);
+
+    sim.host("server", || async {
+        STARTS.fetch_add(1, Ordering::Relaxed);
+        std::future::pending::<()>().await;
+        Ok(())
+    });
+    sim.client("client", async {
+        sleep(Duration::from_secs(1)).await;
+        Ok(())
+    });
+
+    // The second call replaces the pending restart.
+    sim.restart_after("server", Duration::from_millis(100));
+    sim.restart_after("server", Duration::from_millis(300));
+    sim.run_for(Duration::from_millis(200)).unwrap();
+    assert!(!sim.is_host_running("server"));
+
+    sim.run().unwrap();
+    assert!(sim.is_host_running("server"));
+    assert_eq!(STARTS.load(Ordering::Relaxed), 1);
+}
+
+#[snowglobe::scene]
+fn link_config(mut sim: Sim) {
+    use turmoil::net::UdpSocket;
+
+    static NEAR_MS: AtomicU64 = AtomicU64::new(0);
+    static FAR_MS: AtomicU64 = AtomicU64::new(0);
+    static LOSSY: AtomicU32 = AtomicU32::new(0);
+
+    sim.host("server", || async {
+        let socket = UdpSocket::bind("0.0.0.0:9000").await?;
+        let mut buf = [0; 16];
+        loop {
+            let (_, from) = socket.recv_from(&mut buf).await?;
+            let elapsed = turmoil::sim_elapsed().unwrap().as_millis() as u64;
+            if from.ip() == turmoil::lookup("near") {
+                NEAR_MS.store(elapsed, Ordering::Relaxed);
+            } else if from.ip() == turmoil::lookup("far") {
+                FAR_MS.store(elapsed, Ordering::Relaxed);
+            } else {
+                LOSSY.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    });
+    for client in ["near", "far", "lossy"] {
+        sim.client(client, async {
+            let socket = UdpSocket::bind("0.0.0.0:9000").await?;
+            for _ in 0..4 {
+                socket.send_to(b"ping", "server:9000").await?;
+                sleep(Duration::from_millis(10)).await;
+            }
+            sleep(Duration::from_secs(1)).await;
+            Ok(())
+        });
+    }
+
+    sim.link("near", "server").latency(Duration::from_millis(1));
+    sim.link("far", "server")
+        .latency(Duration::from_millis(200));
+    sim.link("lossy", "server")
+        .latency(Duration::from_millis(1))
+        .fail_rate(1.0);
+
+    sim.run().unwrap();
+    let (near, far) = (
+        NEAR_MS.load(Ordering::Relaxed),
+        FAR_MS.load(Ordering::Relaxed),
+    );
+    assert!(near > 0 && near < 100, "near link delivered at {near}ms");
+    assert!(far >= 200, "far link delivered at {far}ms");
+    assert!(
+        LOSSY.load(Ordering::Relaxed) < 4,
+        "failing link dropped no messages"
+    );
+}
+
+#[snowglobe::scene]
+fn nemesis(mut sim: Sim) {
+    let hosts = ["n1", "n2", "n3"];
+    for host in hosts {
+        sim.host(host, std::future::pending);
+    }
+    sim.client("client", async {
+        sleep(Duration::from_secs(8)).await;
+        Ok(())
+    });
+
+    sim.nemesis(
+        Nemesis::builder()
+            .hosts(hosts)
+            .interval(Duration::from_millis(100), Duration::from_millis(500))
+            .stop_after(Duration::from_secs(5))
+            .build(),
+    );
+    sim.run().unwrap();
+
+    for host in hosts {
+        assert!(sim.is_host_running(host));
+    }
+}
+
+#[snowglobe::scene]
+fn invariant_holds(mut sim: Sim) {
+    sim.client("client", async {
+        sleep(Duration::from_secs(1)).await;
+        Ok(())
+    });
+
+    sim.invariant("time_bounded", |sim| {
+        sim.elapsed() <= Duration::from_secs(2)
+    });
+    sim.invariant("always_ok", |_| Ok(()));
+    sim.run().unwrap();
+}
+
+#[snowglobe::scene]
+fn invariant_violated(mut sim: Sim) {
+    static COUNTER: AtomicU32 = AtomicU32::new(0);
+
+    sim.client("client", async {
+        for _ in 0..10 {
+            COUNTER.fetch_add(1, Ordering::Relaxed);
+            sleep(Duration::from_millis(10)).await;
+        }
+        Ok(())
+    });
+
+    sim.invariant("counter_below_five", |_| {
+        COUNTER.load(Ordering::Relaxed) < 5
+    });
+    sim.run().unwrap();
+}
+
+#[snowglobe::scene(simulation_duration = "60s")]
+fn run_phases(mut sim: Sim) {
+    sim.client("client", async {
+        sleep(Duration::from_secs(30)).await;
+        Ok(())
+    });
+
+    sim.run_for(Duration::from_secs(10)).unwrap();
+    assert_eq!(sim.elapsed(), Duration::from_secs(10));
+
+    sim.run_until(|sim| sim.elapsed() >= Duration::from_secs(15))
+        .unwrap();
+    assert_eq!(sim.elapsed(), Duration::from_secs(15));
+
+    sim.run_until(|_| true).unwrap();
+    assert_eq!(sim.elapsed(), Duration::from_secs(15));
+
+    sim.run_until_quiescent().unwrap();
+    assert_eq!(sim.elapsed(), Duration::from_millis(15_001));
+}
+
+#[snowglobe::scene]
+fn run_after_finish(mut sim: Sim) {
+    sim.client("client", async {
+        sleep(Duration::from_secs(1)).await;
+        Ok(())
+    });
+
+    // Running stops once all clients finish.
+    sim.run_for(Duration::from_secs(10)).unwrap();
+    assert!(sim.elapsed() < Duration::from_secs(2));
+    assert!(sim.run_until(|_| false).is_err());
+    sim.run_until_quiescent().unwrap();
+}
+
+#[snowglobe::scene]
+fn quiescent_while_held(mut sim: Sim) {
+    use turmoil::net::UdpSocket;
+
+    sim.host("server", || async {
+        let _socket = UdpSocket::bind("0.0.0.0:9000").await?;
+        std::future::pending().await
+    });
+    sim.client("client", async {
+        let socket = UdpSocket::bind("0.0.0.0:0").await?;
+        socket.send_to(b"ping", "server:9000").await?;
+        sleep(Duration::from_secs(10)).await;
+        Ok(())
+    });
+
+    // Held messages are not waited for.
+    sim.hold("client", "server");
+    sim.run_until_quiescent().unwrap();
+    assert!(sim.elapsed() < Duration::from_secs(10));
+}
+
+#[snowglobe::scene]
+fn message_tap(mut sim: Sim) {
+    use std::cell::RefCell;
+    use std::collections::BTreeMap;
+    use std::rc::Rc;
+
+    use snowglobe::MessageEventKind::*;
+    use turmoil::net::UdpSocket;
+
+    sim.host("server", || async {
+        let socket = UdpSocket::bind("0.0.0.0:9000").await?;
+        let mut buf = [0; 16];
+        loop {
+            socket.recv_from(&mut buf).await?;
+        }
+    });
+    sim.client("client", async {
+        let socket = UdpSocket::bind("0.0.0.0:0").await?;
+        for _ in 0..3 {
+            socket.send_to(b"ping", "server:9000").await?;
+            sleep(Duration::from_secs(1)).await;
+        }
+        Ok(())
+    });
+
+    let counts = Rc::new(RefCell::new(BTreeMap::new()));
+    sim.on_message({
+        let counts = Rc::clone(&counts);
+        move |event| {
+            *counts.borrow_mut().entry(event.kind).or_insert(0) += 1;
+        }
+    });
+
+    sim.run_for(Duration::from_millis(1500)).unwrap();
+    sim.partition("client", "server");
+    sim.run().unwrap();
+
+    let counts = counts.borrow();
+    assert_eq!(counts.get(&Sent), Some(&3));
+    assert_eq!(counts.get(&Delivered), Some(&2));
+    assert_eq!(counts.get(&Dropped), Some(&1));
+}
+
+/// Every kind of message event is observed. Fails if turmoil changes the traces the tap matches.
+#[snowglobe::scene]
+fn message_kinds(mut sim: Sim) {
+    use std::cell::RefCell;
+    use std::collections::BTreeSet;
+    use std::rc::Rc;
+
+    use snowglobe::MessageEventKind::*;
+    use turmoil::net::UdpSocket;
+
+    sim.host("server", || async {
+        let socket = UdpSocket::bind("0.0.0.0:9000").await?;
+        let mut buf = [0; 16];
+        loop {
+            socket.recv_from(&mut buf).await?;
+        }
+    });
+    sim.client("client", async {
+        let socket = UdpSocket::bind("0.0.0.0:0").await?;
+        for _ in 0..2 {
+            socket.send_to(b"ping", "server:9000").await?;
+            sleep(Duration::from_secs(1)).await;
+        }
+        Ok(())
+    });
+
+    let kinds = Rc::new(RefCell::new(BTreeSet::new()));
+    sim.on_message({
+        let kinds = Rc::clone(&kinds);
+        move |event| {
+            kinds.borrow_mut().insert(event.kind);
+        }
+    });
+
+    sim.hold("client", "server");
+    sim.run_for(Duration::from_millis(500)).unwrap();
+    sim.release("client", "server");
+    sim.run_for(Duration::from_millis(500)).unwrap();
+    sim.partition("client", "server");
+    sim.run().unwrap();
+
+    assert_eq!(
+        *kinds.borrow(),
+        BTreeSet::from([Sent, Held, Delivered, Dropped]),
+        "turmoil's message traces changed, update `tap::TapLayer`"
+    );
+}
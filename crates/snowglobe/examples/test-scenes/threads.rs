@@ -0,0 +1,163 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, mpsc};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use snowglobe::Sim;
+
+#[snowglobe::scene(threads = "green")]
+fn spawn_join(_sim: Sim) {
+    let handle = thread::spawn(|| 6 * 7);
+    assert_eq!(handle.join().unwrap(), 42);
+}
+
+#[cfg(target_os = "linux")]
+#[snowglobe::scene(threads = "green")]
+fn thread_ids(_sim: Sim) {
+    assert_eq!(unsafe { libc::gettid() }, 12345);
+    let handles: Vec<_> = (0..2)
+        .map(|_| thread::spawn(|| unsafe { libc::gettid() }))
+        .collect();
+    let tids: Vec<_> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+    assert_eq!(tids, [1_000_001, 1_000_002]);
+}
+
+#[snowglobe::scene(threads = "green")]
+fn panic_join(_sim: Sim) {
+    let handle = thread::spawn(|| panic!("boom"));
+    assert!(handle.join().is_err());
+}
+
+#[snowglobe::scene(threads = "green")]
+fn channel(_sim: Sim) {
+    let (tx, rx) = mpsc::channel();
+    for i in 0..4 {
+        let tx = tx.clone();
+        thread::spawn(move || tx.send(i).unwrap());
+    }
+    drop(tx);
+
+    let mut received: Vec<_> = rx.iter().collect();
+    received.sort();
+    assert_eq!(received, [0, 1, 2, 3]);
+}
+
+#[snowglobe::scene(threads = "green")]
+fn mutex_condvar(_sim: Sim) {
+    let pair = Arc::new((Mutex::new(0), Condvar::new()));
+    let handles: Vec<_> = (0..4)
+        .map(|_| {
+            let pair = pair.clone();
+            thread::spawn(move || {
+                let (lock, cvar) = &*pair;
+                *lock.lock().unwrap() += 1;
+                cvar.notify_all();
+            })
+        })
+        .collect();
+
+    let (lock, cvar) = &*pair;
+    let count = cvar.wait_while(lock.lock().unwrap(), |n| *n < 4).unwrap();
+    assert_eq!(*count, 4);
+    drop(count);
+    for handle in handles {
+        handle.join().unwrap();
+    }
+}
+
+#[snowglobe::scene(threads = "green")]
+fn background_thread(mut sim: Sim) {
+    let ticks = Arc::new(AtomicUsize::new(0));
+
+    let host_ticks = ticks.clone();
+    sim.client("client", async move {
+        let ticks = host_ticks.clone();
+        thread::spawn(move || {
+            let start = Instant::now();
+            loop {
+                thread::sleep(Duration::from_millis(100));
+                ticks.fetch_add(1, Ordering::SeqCst);
+                assert!(start.elapsed() >= Duration::from_millis(100));
+            }
+        });
+        tokio::time::sleep(Duration::from_millis(1050)).await;
+        Ok(())
+    });
+    sim.run().unwrap();
+
+    assert_eq!(ticks.load(Ordering::SeqCst), 10);
+}
+
+#[snowglobe::scene(threads = "green")]
+fn thread_dies_with_host(mut sim: Sim) {
+    let ticks = Arc::new(AtomicUsize::new(0));
+
+    let host_ticks = ticks.clone();
+    sim.host("server", move || {
+        let ticks = host_ticks.clone();
+        async move {
+            thread::spawn(move || {
+                loop {
+                    thread::sleep(Duration::from_millis(100));
+                    ticks.fetch_add(1, Ordering::SeqCst);
+                }
+            });
+            std::future::pending().await
+        }
+    });
+    sim.run_for(Duration::from_millis(550)).unwrap();
+    sim.crash("server");
+    sim.run_for(Duration::from_secs(1)).unwrap();
+
+    assert_eq!(ticks.load(Ordering::SeqCst), 5);
+}
+
+#[snowglobe::scene(threads = "green")]
+fn deadlock(_sim: Sim) {
+    let (_tx, rx) = mpsc::channel::<()>();
+    thread::spawn(move || rx.recv().unwrap());
+    let (tx, rx) = mpsc::channel::<()>();
+    let _ = rx.recv();
+    drop(tx);
+}
+
+#[snowglobe::scene(threads = "green")]
+fn spawn_blocking(mut sim: Sim) {
+    sim.client("client", async {
+        let value = tokio::task::spawn_blocking(|| 6 * 7).await?;
+        assert_eq!(value, 42);
+        let handles: Vec<_> = (0..3)
+            .map(|i| tokio::task::spawn_blocking(move || i))
+            .collect();
+        for (i, handle) in handles.into_iter().enumerate() {
+            assert_eq!(handle.await?, i);
+        }
+        Ok(())
+    });
+    sim.run().unwrap();
+}
+
+#[snowglobe::scene(threads = "green")]
+fn tokio_fs(mut sim: Sim) {
+    sim.client("client", async {
+        tokio::fs::write("/data", b"hello").await?;
+        assert_eq!(tokio::fs::read("/data").await?, b"hello");
+        // The blocking thread used the filesystem of the host that spawned it.
+        assert_eq!(std::fs::read("/data")?, b"hello");
+        Ok(())
+    });
+    sim.run().unwrap();
+}
+
+#[snowglobe::scene(threads = "green")]
+fn spawn_blocking_crash(mut sim: Sim) {
+    sim.host("server", || async {
+        tokio::task::spawn_blocking(|| {}).await?;
+        std::future::pending().await
+    });
+    sim.run_for(Duration::from_millis(100)).unwrap();
+    sim.crash("server");
+    sim.run_for(Duration::from_millis(100)).unwrap();
+    sim.bounce("server");
+    sim.run_for(Duration::from_secs(20)).unwrap();
+}
@@ -232,7 +232,7 @@ fn alloc_heap() -> &'static mut [MaybeUninit<u8>] {
     unsafe { slice::from_raw_parts_mut(base.cast(), HEAP_SIZE) }
 }
 
-struct Stderr;
+pub(crate) struct Stderr;
 
 impl Write for Stderr {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
@@ -0,0 +1,79 @@
+//! Fault injection points for application code.
+
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use rand::Rng;
+use tracing::{debug, trace};
+
+use crate::context;
+
+/// Probability that a buggify site is enabled for a run.
+const SITE_ENABLE_PROBABILITY: f64 = 0.25;
+
+/// Returns `true` if a fault should be injected at this point.
+///
+/// Each call site is enabled or disabled once per run. Enabled sites fire with the given
+/// probability (default: 0.25), clamped to `0.0..=1.0`. All random choices are drawn from the
+/// simulation RNG. Outside of a simulation, `buggify!` always returns `false`.
+#[macro_export]
+macro_rules! buggify {
+    () => {
+        $crate::buggify!($crate::__private::BuggifySite::DEFAULT_PROBABILITY)
+    };
+    ($probability:expr $(,)?) => {{
+        static SITE: $crate::__private::BuggifySite =
+            $crate::__private::BuggifySite::new(concat!(file!(), ":", line!(), ":", column!()));
+        SITE.fire($probability)
+    }};
+}
+
+const UNDECIDED: u8 = 0;
+const ENABLED: u8 = 1;
+const DISABLED: u8 = 2;
+
+pub struct BuggifySite {
+    location: &'static str,
+    state: AtomicU8,
+}
+
+impl BuggifySite {
+    /// Probability that an enabled site fires if `buggify!` is not given one.
+    pub const DEFAULT_PROBABILITY: f64 = 0.25;
+
+    pub const fn new(location: &'static str) -> Self {
+        Self {
+            location,
+            state: AtomicU8::new(UNDECIDED),
+        }
+    }
+
+    pub fn fire(&self, probability: f64) -> bool {
+        if !context::with(|ctx| ctx.in_simulation) {
+            return false;
+        }
+
+        let enabled = match self.state.load(Ordering::Relaxed) {
+            ENABLED => true,
+            DISABLED => false,
+            _ => {
+                let enabled = context::with(|ctx| ctx.rng.random_bool(SITE_ENABLE_PROBABILITY));
+                let state = if enabled { ENABLED } else { DISABLED };
+                self.state.store(state, Ordering::Relaxed);
+                debug!(location = self.location, enabled, "buggify site decided");
+                enabled
+            }
+        };
+
+        // `random_bool` panics on probabilities outside of `0.0..=1.0`, including NaN.
+        let probability = if probability.is_nan() {
+            0.0
+        } else {
+            probability.clamp(0.0, 1.0)
+        };
+        let fire = enabled && context::with(|ctx| ctx.rng.random_bool(probability));
+        if fire {
+            trace!(location = self.location, "buggify fired");
+        }
+        fire
+    }
+}
@@ -1,7 +1,14 @@
 use std::collections::BTreeMap;
-use std::time::Duration;
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::process;
+use std::sync::OnceLock;
+use std::time::{Duration, UNIX_EPOCH};
 
-use crate::{Result, context};
+use crate::log::{self, LogFormat};
+use crate::vfs::DiskFaults;
+use crate::{Result, context, event, property, seccomp, tap};
 
 use __private::*;
 use snowglobe_proto as proto;
@@ -11,6 +18,9 @@ use tracing::info;
 #[derive(argh::FromArgs)]
 /// Run snowglobe simulations
 struct Args {
+    /// log output format: text or json (default: text)
+    #[argh(option, default = "LogFormat::Text")]
+    log_format: LogFormat,
     #[argh(subcommand)]
     command: Command,
 }
@@ -37,11 +47,40 @@ struct RunArgs {
     /// RNG seed for the simulation
     #[argh(option)]
     rng_seed: u64,
+    /// file to write simulation events to
+    #[argh(option)]
+    events: Option<PathBuf>,
+    /// file to write the run report to
+    #[argh(option)]
+    report: Option<PathBuf>,
+    /// file to write captured network messages to
+    #[argh(option)]
+    capture: Option<PathBuf>,
+    /// wall-clock time at the start of the simulation, as an RFC 3339 timestamp (overrides the
+    /// scene's `epoch`)
+    #[argh(option, from_str_fn(parse_epoch))]
+    epoch: Option<Duration>,
+    /// fail the run if the scene makes system calls that bypass snowglobe's patches (Linux only)
+    #[argh(switch)]
+    strict: bool,
+}
+
+/// Wall-clock time at the start of the simulation if neither the scene nor the command line
+/// specify one: 2025-01-01T00:00:00Z.
+const DEFAULT_EPOCH: Duration = Duration::from_secs(1_735_689_600);
+
+/// Number of CPUs reported to the simulated program if the scene doesn't specify one.
+const DEFAULT_CPUS: usize = 4;
+
+fn parse_epoch(s: &str) -> std::result::Result<Duration, String> {
+    let time = humantime::parse_rfc3339_weak(s).map_err(|e| e.to_string())?;
+    time.duration_since(UNIX_EPOCH)
+        .map_err(|_| "epoch must not be before 1970".into())
 }
 
 pub fn main() -> Result {
     let args: Args = argh::from_env();
-    init_logging();
+    log::init(args.log_format);
 
     match args.command {
         Command::Info(_args) => info(),
@@ -51,20 +90,6 @@ pub fn main() -> Result {
     Ok(())
 }
 
-fn init_logging() {
-    use tracing::level_filters::LevelFilter;
-    use tracing_subscriber::EnvFilter;
-
-    let env_filter = EnvFilter::builder()
-        .with_default_directive(LevelFilter::INFO.into())
-        .from_env_lossy();
-
-    tracing_subscriber::fmt()
-        .with_writer(std::io::stderr)
-        .with_env_filter(env_filter)
-        .init();
-}
-
 fn info() {
     let scenes = scenes().keys().cloned().collect();
     let info = proto::Info { scenes };
@@ -77,19 +102,87 @@ fn run(args: RunArgs) -> Result {
     let scene = scenes.get(&args.scene).ok_or("scene does not exist")?;
 
     let rng_seed = args.rng_seed;
-    info!(scene = args.scene, rng_seed, "running simulation");
+    let epoch = args.epoch.or(scene.config.epoch).unwrap_or(DEFAULT_EPOCH);
+    info!(scene = args.scene, rng_seed, ?epoch, "running simulation");
+
+    if let Some(path) = &args.events {
+        event::init_sink(path)?;
+    }
+
+    if args.capture.is_some() {
+        tap::start_capture();
+    }
 
     context::init_rng(rng_seed);
-    run_scene(scene, rng_seed);
+    context::init_time(epoch);
+    context::init_sleep(scene.config.sleep.unwrap_or_default());
+    context::init_disk(disk_faults(&scene.config));
+    context::init_env(scene.config.env.unwrap_or_default());
+    context::init_threads(scene.config.threads.unwrap_or_default());
+    context::init_cpus(scene.config.cpus.unwrap_or(DEFAULT_CPUS));
+    context::enter_simulation();
+    if args.strict {
+        seccomp::install()?;
+    }
+    let outputs = OUTPUTS.get_or_init(|| Outputs {
+        report: args.report,
+        capture: args.capture,
+    });
+    let result = panic::catch_unwind(AssertUnwindSafe(|| run_scene(scene, rng_seed, epoch)));
+    outputs.write()?;
+
+    if let Err(payload) = result {
+        panic::resume_unwind(payload);
+    }
 
     Ok(())
 }
 
-fn run_scene(scene: &Scene, rng_seed: u64) {
+/// Files the run writes its outputs to.
+struct Outputs {
+    report: Option<PathBuf>,
+    capture: Option<PathBuf>,
+}
+
+impl Outputs {
+    /// Write the outputs. This is done even if the scene failed, so failed runs still count
+    /// towards property coverage and their network traffic can be inspected.
+    fn write(&self) -> Result {
+        if let Some(path) = &self.report {
+            let report = property::report();
+            fs::write(path, report.serialize())?;
+        }
+        if let Some(path) = &self.capture {
+            tap::write_capture(path)?;
+        }
+        event::close_sink()?;
+        Ok(())
+    }
+}
+
+/// Outputs of the run, kept for runs that fail without returning.
+static OUTPUTS: OnceLock<Outputs> = OnceLock::new();
+
+/// Fail the run where the failure can neither be returned nor panicked with, like in a patch
+/// called from C that can't return: unwinding out of it would abort the process.
+///
+/// Writes the run's outputs, as a failed scene would, and exits.
+pub(crate) fn fail(reason: &str) -> ! {
+    eprintln!("{reason}");
+    if let Some(outputs) = OUTPUTS.get()
+        && let Err(err) = outputs.write()
+    {
+        eprintln!("failed to write the run's outputs: {err}");
+    }
+    process::exit(101);
+}
+
+fn run_scene(scene: &Scene, rng_seed: u64, epoch: Duration) {
     let mut builder = turmoil::Builder::new();
     builder.enable_random_order();
     builder.tick_duration(Duration::from_millis(1));
     builder.rng_seed(rng_seed);
+    builder.epoch(UNIX_EPOCH + epoch);
 
     macro_rules! apply_config {
         ($cfg:expr, $builder:expr, [$( $arg:ident, )*]) => {
@@ -114,6 +207,16 @@ fn run_scene(scene: &Scene, rng_seed: u64) {
     (scene.func)(sim);
 }
 
+fn disk_faults(config: &SceneConfig) -> DiskFaults {
+    DiskFaults {
+        eio_rate: config.disk_eio_rate.unwrap_or_default(),
+        enospc_rate: config.disk_enospc_rate.unwrap_or_default(),
+        edquot_rate: config.disk_edquot_rate.unwrap_or_default(),
+        short_io_rate: config.disk_short_io_rate.unwrap_or_default(),
+        latency: config.disk_latency.unwrap_or_default(),
+    }
+}
+
 fn scenes() -> BTreeMap<String, &'static Scene> {
     SCENES
         .iter()
@@ -133,10 +236,19 @@ pub mod __private {
     use std::time::Duration;
 
     pub use linkme;
+    pub use snowglobe_proto::PropertyKind;
+
+    pub use crate::buggify::BuggifySite;
+    pub use crate::clock::SleepMode;
+    pub use crate::green::ThreadMode;
+    pub use crate::property::Property;
 
     #[linkme::distributed_slice]
     pub static SCENES: [Scene];
 
+    #[linkme::distributed_slice]
+    pub static PROPERTIES: [Property];
+
     pub struct Scene {
         pub module: &'static str,
         pub name: &'static str,
@@ -152,5 +264,18 @@ pub mod __private {
         pub max_message_latency: Option<Duration>,
         pub fail_rate: Option<f64>,
         pub repair_rate: Option<f64>,
+        /// Wall-clock time at the start of the simulation, as a duration since the Unix epoch.
+        pub epoch: Option<Duration>,
+        pub sleep: Option<SleepMode>,
+        pub disk_eio_rate: Option<f64>,
+        pub disk_enospc_rate: Option<f64>,
+        pub disk_edquot_rate: Option<f64>,
+        pub disk_short_io_rate: Option<f64>,
+        pub disk_latency: Option<Duration>,
+        /// Environment variables, replacing the real environment of the process.
+        pub env: Option<&'static [(&'static str, &'static str)]>,
+        /// Number of CPUs reported to the simulated program.
+        pub cpus: Option<usize>,
+        pub threads: Option<ThreadMode>,
     }
 }
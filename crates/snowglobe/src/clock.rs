@@ -0,0 +1,135 @@
+//! Per-host clock skew and drift.
+
+use std::time::Duration;
+
+use rand::Rng;
+
+use crate::context;
+
+/// Clock configuration of a simulated host, relative to the simulation's reference time.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Clock {
+    /// Offset of the wall clock, in nanoseconds.
+    offset: i128,
+    /// Rate at which the clock runs fast (positive) or slow (negative).
+    drift: f64,
+}
+
+impl Clock {
+    /// A clock that is ahead of the reference time by the given offset.
+    pub fn ahead(offset: Duration) -> Self {
+        Self {
+            offset: offset.as_nanos() as i128,
+            drift: 0.,
+        }
+    }
+
+    /// A clock that is behind the reference time by the given offset.
+    pub fn behind(offset: Duration) -> Self {
+        Self {
+            offset: -(offset.as_nanos() as i128),
+            drift: 0.,
+        }
+    }
+
+    /// A clock with an offset and drift drawn from the simulation RNG.
+    ///
+    /// The offset is chosen from `-max_offset..=max_offset` and the drift from
+    /// `-max_drift..=max_drift`, where `max_drift` must be at least 0 and less than 1.
+    pub fn random(max_offset: Duration, max_drift: f64) -> Self {
+        assert!(
+            (0. ..1.).contains(&max_drift),
+            "maximum clock drift must be at least 0 and less than 1"
+        );
+        let max_offset = max_offset.as_nanos() as i128;
+        let (offset, drift) = context::with(|ctx| {
+            (
+                ctx.rng.random_range(-max_offset..=max_offset),
+                ctx.rng.random_range(-max_drift..=max_drift),
+            )
+        });
+        Self { offset, drift: 0. }.drift(drift)
+    }
+
+    /// Set the drift rate of the clock.
+    ///
+    /// A drift of `1e-4` makes the clock gain 100µs per simulated second, a drift of `-1e-4`
+    /// makes it lose as much.
+    pub fn drift(mut self, rate: f64) -> Self {
+        assert!(rate > -1., "clock drift must be greater than -1");
+        self.drift = rate;
+        self
+    }
+}
+
+/// How blocking sleeps (`nanosleep`, `sleep`, ...) behave in a scene.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SleepMode {
+    /// Fail the run, as sleeping would block the simulation.
+    #[default]
+    Deny,
+    /// Return immediately, advancing the clocks of the sleeping host.
+    Advance,
+}
+
+/// The clock state of a simulated host.
+pub(crate) struct HostClock {
+    clock: Clock,
+    /// Simulated time elapsed when the clock was set.
+    set_at: Duration,
+    /// Value of the host's monotonic clock when the clock was set.
+    monotonic_at_set: Duration,
+    /// Time the host's clocks were advanced by its sleeps, which setting the clock keeps.
+    slept: Duration,
+}
+
+impl HostClock {
+    /// Create the state of a clock that follows the reference time, from `elapsed` on.
+    pub fn new(elapsed: Duration) -> Self {
+        Self {
+            clock: Clock::default(),
+            set_at: elapsed,
+            monotonic_at_set: elapsed,
+            slept: Duration::ZERO,
+        }
+    }
+
+    /// Set the clock at `elapsed`, its monotonic clock continuing from its current value.
+    pub fn set(&mut self, clock: Clock, elapsed: Duration) {
+        self.monotonic_at_set = self.monotonic(elapsed);
+        self.set_at = elapsed;
+        self.clock = clock;
+    }
+
+    /// Advance the host's clocks by the given duration.
+    pub fn advance(&mut self, duration: Duration) {
+        self.slept += duration;
+        self.monotonic_at_set += duration;
+    }
+
+    /// Read the host's wall clock, given the reference time.
+    ///
+    /// Setting the clock makes the wall clock jump to the new offset, as an NTP correction would.
+    pub fn realtime(&self, time: Duration, elapsed: Duration) -> Duration {
+        let since_set = elapsed.saturating_sub(self.set_at);
+        let drifted = since_set.as_nanos() as f64 * self.clock.drift;
+        let nanos = (time + self.slept).as_nanos() as i128 + self.clock.offset + drifted as i128;
+        duration_from_nanos(nanos)
+    }
+
+    /// Read the host's monotonic clock.
+    ///
+    /// The monotonic clock is affected by drift, but never jumps when the clock is set.
+    pub fn monotonic(&self, elapsed: Duration) -> Duration {
+        let since_set = elapsed.saturating_sub(self.set_at);
+        self.monotonic_at_set + since_set.mul_f64(1. + self.clock.drift)
+    }
+}
+
+/// Convert nanoseconds to a duration, saturating at zero.
+fn duration_from_nanos(nanos: i128) -> Duration {
+    let nanos = nanos.max(0) as u128;
+    let secs = (nanos / 1_000_000_000) as u64;
+    let subsec = (nanos % 1_000_000_000) as u32;
+    Duration::new(secs, subsec)
+}
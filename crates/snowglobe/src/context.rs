@@ -1,43 +1,250 @@
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use std::mem::ManuallyDrop;
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, Ordering};
 use std::time::Duration;
 
+use libc::pid_t;
 use rand::SeedableRng;
 use rand::rngs::SmallRng;
 
+use crate::clock::{HostClock, SleepMode};
+use crate::env::Env;
+use crate::green::{Scheduler, ThreadMode};
+use crate::patch::rng::LegacyRng;
+use crate::vfs::{DiskFaults, Vfs};
+
 thread_local! {
-    static CONTEXT: RefCell<Context> = RefCell::new(Context::new());
+    // The context is never dropped, so the patched allocator can still use it while other
+    // thread-locals are being destroyed.
+    static CONTEXT: RefCell<ManuallyDrop<Context>> = RefCell::new(ManuallyDrop::new(Context::new()));
+
+    /// Whether this thread is a green thread, using the context of the main thread.
+    static GREEN: Cell<bool> = const { Cell::new(false) };
 }
 
+/// Context of the main thread, shared with green threads.
+static MAIN_CONTEXT: AtomicPtr<RefCell<ManuallyDrop<Context>>> = AtomicPtr::new(ptr::null_mut());
+
+/// Process id of the scene bundle, as seen outside of hosts.
+const BUNDLE_PID: pid_t = 12345;
+
 pub(crate) struct Context {
+    pub in_simulation: bool,
+    pub seed: u64,
     pub rng: SmallRng,
+    /// State of libc's `rand` and `drand48` families.
+    pub legacy_rng: LegacyRng,
+    /// Simulated wall-clock time, as a duration since the Unix epoch.
     pub time: Duration,
+    /// Simulated time elapsed since the start of the simulation.
+    pub elapsed: Duration,
+    /// Name of the simulated host that is currently running, if any.
+    pub host: Option<String>,
+    /// Clocks of hosts that don't follow the reference time.
+    pub clocks: BTreeMap<String, HostClock>,
+    pub sleep: SleepMode,
+    /// Filesystems of the simulated hosts.
+    pub vfs: Vfs,
+    /// Environments of the scene and the simulated hosts.
+    pub env: Env,
+    /// Process ids of the simulated hosts, in the order they were added.
+    pub pids: BTreeMap<String, pid_t>,
+    /// Number of CPUs reported to the simulated program.
+    pub cpus: usize,
+    /// Green threads spawned by the simulated program.
+    pub threads: Scheduler,
+    /// Why the run fails, when it is detected where an error can't be returned.
+    pub failure: Option<&'static str>,
 }
 
 impl Context {
     fn new() -> Self {
         Self {
+            in_simulation: false,
+            seed: 0,
             rng: SmallRng::seed_from_u64(0),
+            legacy_rng: LegacyRng::new(0),
             time: Duration::ZERO,
+            elapsed: Duration::ZERO,
+            host: None,
+            clocks: BTreeMap::new(),
+            sleep: SleepMode::Deny,
+            vfs: Vfs::default(),
+            env: Env::default(),
+            pids: BTreeMap::new(),
+            cpus: 1,
+            threads: Scheduler::default(),
+            failure: None,
         }
     }
+
+    /// Wall-clock time as seen by the current host.
+    pub fn realtime(&self) -> Duration {
+        match self.host_clock() {
+            Some(clock) => clock.realtime(self.time, self.elapsed),
+            None => self.time,
+        }
+    }
+
+    /// Monotonic time as seen by the current host.
+    pub fn monotonic(&self) -> Duration {
+        match self.host_clock() {
+            Some(clock) => clock.monotonic(self.elapsed),
+            None => self.elapsed,
+        }
+    }
+
+    /// Advance the clocks of the current host, as if it slept for the given duration.
+    ///
+    /// Returns `false` if no host is running.
+    pub fn advance_host_clock(&mut self, duration: Duration) -> bool {
+        let Some(host) = &self.host else {
+            return false;
+        };
+
+        let elapsed = self.elapsed;
+        self.clocks
+            .entry(host.clone())
+            .or_insert_with(|| HostClock::new(elapsed))
+            .advance(duration);
+        true
+    }
+
+    /// Process id of the current host, or of the scene bundle outside of hosts.
+    pub fn pid(&self) -> pid_t {
+        self.host
+            .as_ref()
+            .and_then(|host| self.pids.get(host))
+            .copied()
+            .unwrap_or(BUNDLE_PID)
+    }
+
+    fn host_clock(&self) -> Option<&HostClock> {
+        self.clocks.get(self.host.as_deref()?)
+    }
 }
 
 pub(crate) fn with<F, R>(f: F) -> R
 where
     F: FnOnce(&mut Context) -> R,
 {
-    CONTEXT.with_borrow_mut(f)
+    if GREEN.get() {
+        // Only one thread runs at a time, see `green`.
+        let ctx = unsafe { &*MAIN_CONTEXT.load(Ordering::Acquire) };
+        return f(&mut ctx.borrow_mut());
+    }
+    CONTEXT.with_borrow_mut(|ctx| f(ctx))
+}
+
+/// Mark the calling thread as a green thread.
+pub(crate) fn set_green() {
+    GREEN.set(true);
+}
+
+/// Whether the calling thread is a green thread.
+pub(crate) fn is_green() -> bool {
+    GREEN.get()
 }
 
 pub(crate) fn init_rng(seed: u64) {
     with(|ctx| {
+        ctx.seed = seed;
         ctx.rng = SmallRng::seed_from_u64(seed);
+        ctx.legacy_rng = LegacyRng::new(seed);
+    });
+}
+
+pub(crate) fn enter_simulation() {
+    CONTEXT.with(|ctx| MAIN_CONTEXT.store(ptr::from_ref(ctx).cast_mut(), Ordering::Release));
+    with(|ctx| {
+        ctx.in_simulation = true;
     });
 }
 
-pub(crate) fn advance_time(new_time: Duration) {
+pub(crate) fn init_sleep(mode: SleepMode) {
+    with(|ctx| {
+        ctx.sleep = mode;
+    });
+}
+
+/// Hide the real environment of the process behind the scene's.
+pub(crate) fn init_env(vars: &[(&str, &str)]) {
+    with(|ctx| ctx.env.init(vars));
+}
+
+/// Set how threads spawned by the simulated program behave.
+pub(crate) fn init_threads(mode: ThreadMode) {
+    with(|ctx| {
+        ctx.threads.mode = mode;
+    });
+}
+
+/// Set the number of CPUs reported to the simulated program.
+pub(crate) fn init_cpus(cpus: usize) {
+    with(|ctx| {
+        ctx.cpus = cpus;
+    });
+}
+
+/// Give a newly added host the next process id, keeping the one it has if it was added before.
+pub(crate) fn register_host(name: &str) {
+    with(|ctx| {
+        let pid = BUNDLE_PID + 1 + ctx.pids.len() as pid_t;
+        ctx.pids.entry(name.into()).or_insert(pid);
+    });
+}
+
+/// Set the faults injected into the disks of all hosts.
+pub(crate) fn init_disk(faults: DiskFaults) {
+    with(|ctx| {
+        ctx.vfs.faults = faults;
+    });
+}
+
+/// Set the wall-clock time at the start of the simulation.
+pub(crate) fn init_time(epoch: Duration) {
+    with(|ctx| {
+        ctx.time = epoch;
+        ctx.elapsed = Duration::ZERO;
+    });
+}
+
+pub(crate) fn advance_time(new_time: Duration, elapsed: Duration) {
     with(|ctx| {
         assert!(ctx.time <= new_time);
         ctx.time = new_time;
+        ctx.elapsed = elapsed;
+    });
+}
+
+/// Fail the run once the current step is over.
+///
+/// For failures detected in patches, where panicking would unwind into C and abort the process.
+pub(crate) fn fail(reason: &'static str) {
+    with(|ctx| {
+        ctx.failure.get_or_insert(reason);
+    });
+}
+
+/// Take the reason the run fails, if any.
+pub(crate) fn take_failure() -> Option<&'static str> {
+    with(|ctx| ctx.failure.take())
+}
+
+/// Mark the given simulated host as the one currently running.
+pub(crate) fn enter_host(name: &str) {
+    with(|ctx| {
+        ctx.env.enter(name);
+        ctx.host = Some(name.into());
+    });
+}
+
+pub(crate) fn exit_host() {
+    with(|ctx| {
+        if let Some(host) = ctx.host.take() {
+            ctx.env.exit(&host);
+        }
     });
 }
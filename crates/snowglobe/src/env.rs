@@ -0,0 +1,198 @@
+//! Process environment seen by scenes and simulated hosts.
+//!
+//! The real environment of the process is hidden once the simulation starts. Code running outside
+//! of hosts sees the scene's environment, and every host sees the scene's environment with its
+//! own variables on top. Switching is done by pointing `environ` at the environment of the host
+//! that is running, so `getenv`, `secure_getenv` and `environ` itself all agree.
+//!
+//! libc's `setenv` reuses the array it allocated last, which may belong to another host by then,
+//! so `setenv` and friends are patched to build a new array instead, see [`set`] and [`remove`].
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::ffi::{CStr, CString};
+use std::ptr;
+use std::sync::Mutex;
+
+use libc::c_char;
+
+type Environ = *mut *mut c_char;
+
+/// Addresses of the arrays built by [`to_environ`] that are still in use.
+static ARRAYS: Mutex<BTreeSet<usize>> = Mutex::new(BTreeSet::new());
+
+/// Environments of the scene and of all simulated hosts.
+pub(crate) struct Env {
+    /// Variables of the scene.
+    vars: BTreeMap<String, String>,
+    /// Variables set or removed for individual hosts, on top of the scene's.
+    host_vars: BTreeMap<String, BTreeMap<String, Option<String>>>,
+    /// Environment of each host's process, as it last left it.
+    processes: BTreeMap<String, Environ>,
+    /// Environment of the code running outside of hosts, saved while a host runs.
+    outside: Environ,
+}
+
+impl Default for Env {
+    fn default() -> Self {
+        Self {
+            vars: BTreeMap::new(),
+            host_vars: BTreeMap::new(),
+            processes: BTreeMap::new(),
+            outside: ptr::null_mut(),
+        }
+    }
+}
+
+impl Env {
+    /// Replace the environment of the process with the scene's.
+    pub fn init(&mut self, vars: &[(&str, &str)]) {
+        self.vars = vars
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        replace(build(&self.vars));
+    }
+
+    /// Set a variable for a host, or remove it if `value` is `None`.
+    ///
+    /// The change takes effect when the host's process is next started.
+    pub fn set(&mut self, host: &str, key: &str, value: Option<&str>) {
+        self.host_vars
+            .entry(host.into())
+            .or_default()
+            .insert(key.into(), value.map(Into::into));
+    }
+
+    /// Switch to the environment of a host, starting from its configured variables if its
+    /// process has not run yet.
+    pub fn enter(&mut self, host: &str) {
+        let process = match self.processes.get(host) {
+            Some(process) => *process,
+            None => {
+                let mut vars = self.vars.clone();
+                for (key, value) in self.host_vars.get(host).into_iter().flatten() {
+                    match value {
+                        Some(value) => vars.insert(key.clone(), value.clone()),
+                        None => vars.remove(key),
+                    };
+                }
+                build(&vars)
+            }
+        };
+
+        unsafe {
+            self.outside = *environ();
+            *environ() = process;
+        }
+    }
+
+    /// Switch back to the environment outside of hosts, saving the one the host left.
+    pub fn exit(&mut self, host: &str) {
+        unsafe {
+            self.processes.insert(host.into(), *environ());
+            *environ() = self.outside;
+        }
+    }
+
+    /// Forget the environment of a host's process, as happens when it exits.
+    pub fn reset(&mut self, host: &str) {
+        if let Some(process) = self.processes.remove(host) {
+            free(process);
+        }
+    }
+}
+
+/// Build a `NULL`-terminated array of `KEY=VALUE` strings.
+fn build(vars: &BTreeMap<String, String>) -> Environ {
+    let entries = vars
+        .iter()
+        .map(|(key, value)| CString::new(format!("{key}={value}")).unwrap().into_raw())
+        .collect();
+    to_environ(entries)
+}
+
+/// Turn entries into a `NULL`-terminated array.
+///
+/// The array is freed once it is replaced, see [`replace`]. Entries are leaked, as the program may
+/// hold on to pointers returned by `getenv` after the environment changed. libc does the same.
+fn to_environ(mut entries: Vec<*mut c_char>) -> Environ {
+    entries.push(ptr::null_mut());
+    let array = Box::into_raw(entries.into_boxed_slice()).cast::<*mut c_char>();
+    ARRAYS.lock().unwrap().insert(array as usize);
+    array
+}
+
+/// Point `environ` at a new array, freeing the one it pointed at if it was built here.
+fn replace(array: Environ) {
+    let old = unsafe { std::mem::replace(&mut *environ(), array) };
+    free(old);
+}
+
+/// Free an array built by [`to_environ`], leaving any other array alone.
+fn free(array: Environ) {
+    if !ARRAYS.lock().unwrap().remove(&(array as usize)) {
+        return;
+    }
+    let mut len = 1;
+    while unsafe { !(*array.add(len - 1)).is_null() } {
+        len += 1;
+    }
+    drop(unsafe { Box::from_raw(ptr::slice_from_raw_parts_mut(array, len)) });
+}
+
+/// Get the entries of the current environment.
+fn entries() -> Vec<*mut c_char> {
+    let mut entries = Vec::new();
+    let mut entry = unsafe { *environ() };
+    while !entry.is_null() && unsafe { !(*entry).is_null() } {
+        entries.push(unsafe { *entry });
+        entry = unsafe { entry.add(1) };
+    }
+    entries
+}
+
+/// Get the name of a `KEY=VALUE` entry.
+fn name(entry: &*mut c_char) -> &[u8] {
+    let entry = unsafe { CStr::from_ptr(*entry) }.to_bytes();
+    entry.split(|b| *b == b'=').next().unwrap()
+}
+
+/// Add a `KEY=VALUE` entry to the current environment, replacing the variable if `overwrite` is
+/// set. The entry itself becomes part of the environment.
+///
+/// Returns whether the entry was added.
+pub(crate) fn set(entry: *mut c_char, overwrite: bool) -> bool {
+    let mut entries = entries();
+    match entries.iter().position(|e| name(e) == name(&entry)) {
+        Some(_) if !overwrite => return false,
+        Some(i) => entries[i] = entry,
+        None => entries.push(entry),
+    }
+    replace(to_environ(entries));
+    true
+}
+
+/// Remove a variable from the current environment.
+pub(crate) fn remove(key: &[u8]) {
+    let mut entries = entries();
+    entries.retain(|e| name(e) != key);
+    replace(to_environ(entries));
+}
+
+/// Remove all variables from the current environment.
+pub(crate) fn clear() {
+    replace(to_environ(Vec::new()));
+}
+
+#[cfg(target_os = "linux")]
+fn environ() -> *mut Environ {
+    unsafe extern "C" {
+        static mut environ: Environ;
+    }
+    &raw mut environ
+}
+
+#[cfg(target_os = "macos")]
+fn environ() -> *mut Environ {
+    unsafe { libc::_NSGetEnviron() }
+}
@@ -0,0 +1,49 @@
+//! Reporting of simulation events.
+
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+
+use snowglobe_proto as proto;
+use snowglobe_proto::Message as _;
+use tracing::{error, info};
+
+use crate::context;
+
+thread_local! {
+    /// File events are written to, or the error that stopped writing to it.
+    static SINK: RefCell<Option<io::Result<File>>> = const { RefCell::new(None) };
+}
+
+/// Start writing events to the file at the given path.
+pub(crate) fn init_sink(path: &Path) -> io::Result<()> {
+    let file = File::create(path)?;
+    SINK.set(Some(Ok(file)));
+    Ok(())
+}
+
+/// Stop writing events, returning the error that stopped writing them earlier, if any.
+pub(crate) fn close_sink() -> io::Result<()> {
+    SINK.take().transpose().map(drop)
+}
+
+/// Emit a simulation event.
+///
+/// The event is logged and, if an event sink is configured, written to it. Failing to write
+/// doesn't interrupt the simulation: no more events are written, and the error is returned by
+/// [`close_sink`].
+pub(crate) fn emit(kind: proto::EventKind) {
+    let time = context::with(|ctx| ctx.elapsed);
+    info!(?time, ?kind, "sim event");
+
+    SINK.with_borrow_mut(|sink| {
+        if let Some(Ok(file)) = sink {
+            let event = proto::Event { time, kind };
+            if let Err(err) = writeln!(file, "{}", event.serialize()) {
+                error!(%err, "writing event failed, no more events are written");
+                *sink = Some(Err(err));
+            }
+        }
+    });
+}
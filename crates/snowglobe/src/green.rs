@@ -0,0 +1,444 @@
+//! Green threads: threads spawned by the simulated program, run one at a time.
+//!
+//! With `#[scene(threads = "green")]`, `pthread_create` starts a real thread that only runs while
+//! it holds the baton, so the program behaves like a set of cooperatively scheduled coroutines
+//! while every thread keeps its own stack and thread-locals. Threads switch at synchronization
+//! points: waiting on or waking a futex (which std's mutexes, condvars, channels and parking are
+//! built on), joining a thread, sleeping, yielding and exiting. The next thread is picked with the
+//! simulation RNG, so interleavings are reproducible from the seed. Between simulation steps,
+//! runnable threads run until all of them are blocked. Threads that finish are parked rather
+//! than exiting, so nothing ever runs alongside the thread holding the baton.
+//!
+//! Tokio's blocking pool is made of such threads, so `spawn_blocking` and what is built on it,
+//! like `tokio::fs`, run their closures at points picked by the simulation RNG. `block_in_place`
+//! is not supported, as it needs tokio's multi-threaded runtime and hosts run on a current-thread
+//! one.
+//!
+//! Green threads share the context of the main thread and belong to the host that spawned them.
+//! They never run again once that host's process exits. Time only passes between steps, so the
+//! main thread can't wait for a thread that waits for time to pass.
+
+use std::cell::UnsafeCell;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use libc::{c_int, c_void, pthread_attr_t, pthread_cond_t, pthread_mutex_t, pthread_t};
+use rand::Rng;
+
+use crate::cli;
+use crate::context::{self, Context};
+
+/// How `pthread_create` behaves in a scene.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ThreadMode {
+    /// Fail with `EPERM`, as a real thread would run outside of the simulation.
+    #[default]
+    Deny,
+    /// Run spawned threads as green threads.
+    Green,
+}
+
+/// Id of the main thread.
+const MAIN: usize = 0;
+
+type StartRoutine = extern "C" fn(*mut c_void) -> *mut c_void;
+
+/// Threads of the simulated program.
+#[derive(Default)]
+pub(crate) struct Scheduler {
+    pub mode: ThreadMode,
+    /// All threads, including the main thread once another one was spawned.
+    threads: BTreeMap<usize, Thread>,
+    /// Thread holding the baton.
+    current: usize,
+    /// Whether all threads blocked, waiting for each other.
+    deadlocked: bool,
+}
+
+struct Thread {
+    pthread: Option<pthread_t>,
+    host: Option<String>,
+    state: State,
+    /// Whether the thread last stopped waiting because its deadline passed.
+    timed_out: bool,
+    /// Value returned by the thread's start routine.
+    ret: usize,
+    baton: &'static Baton,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    Runnable,
+    /// Waiting to be woken through a futex word, or until the deadline on its host's monotonic
+    /// clock.
+    Waiting {
+        futex: Option<usize>,
+        deadline: Option<Duration>,
+    },
+    Joining(usize),
+    /// The main thread, waiting for all other threads to block.
+    Idle,
+    Finished,
+    /// Its host's process exited.
+    Dead,
+}
+
+impl Scheduler {
+    /// Whether threads are scheduled, rather than left to the OS.
+    pub fn is_active(&self) -> bool {
+        !self.threads.is_empty()
+    }
+
+    /// Id of the thread holding the baton, which is the main thread unless threads are scheduled.
+    pub fn current_id(&self) -> usize {
+        self.current
+    }
+
+    fn current(&mut self) -> &mut Thread {
+        self.threads.get_mut(&self.current).unwrap()
+    }
+
+    fn find(&self, pthread: pthread_t) -> Option<usize> {
+        self.threads
+            .iter()
+            .find(|(_, t)| t.pthread == Some(pthread))
+            .map(|(id, _)| *id)
+    }
+
+    /// Make threads runnable again once what they wait for happened.
+    fn wake(&mut self, now: impl Fn(Option<&str>) -> Duration) {
+        let finished: Vec<_> = self
+            .threads
+            .iter()
+            .filter(|(_, t)| t.state == State::Finished)
+            .map(|(id, _)| *id)
+            .collect();
+
+        for thread in self.threads.values_mut() {
+            match thread.state {
+                State::Waiting {
+                    deadline: Some(deadline),
+                    ..
+                } if now(thread.host.as_deref()) >= deadline => {
+                    thread.state = State::Runnable;
+                    thread.timed_out = true;
+                }
+                State::Joining(id) if finished.contains(&id) => thread.state = State::Runnable,
+                _ => {}
+            }
+        }
+    }
+
+    /// Wake up to `count` threads waiting on a futex word, returning how many were woken.
+    fn wake_futex(&mut self, futex: usize, count: usize) -> usize {
+        let mut woken = 0;
+        for thread in self.threads.values_mut() {
+            if woken == count {
+                break;
+            }
+            if let State::Waiting {
+                futex: Some(addr), ..
+            } = thread.state
+                && addr == futex
+            {
+                thread.state = State::Runnable;
+                thread.timed_out = false;
+                woken += 1;
+            }
+        }
+        woken
+    }
+}
+
+/// Pick the thread to run next.
+///
+/// If all threads are blocked, the main thread is picked to report the deadlock.
+fn pick(ctx: &mut Context) -> usize {
+    pick_runnable(ctx).unwrap_or_else(|| {
+        ctx.threads.deadlocked = true;
+        MAIN
+    })
+}
+
+/// Pick a runnable thread, or `None` if all threads are blocked.
+fn pick_runnable(ctx: &mut Context) -> Option<usize> {
+    let elapsed = ctx.elapsed;
+    let clocks = &ctx.clocks;
+    ctx.threads
+        .wake(|host| match host.and_then(|h| clocks.get(h)) {
+            Some(clock) => clock.monotonic(elapsed),
+            None => elapsed,
+        });
+
+    let runnable: Vec<_> = ctx
+        .threads
+        .threads
+        .iter()
+        .filter(|(_, t)| t.state == State::Runnable)
+        .map(|(id, _)| *id)
+        .collect();
+
+    let next = match runnable.len() {
+        0 => {
+            let main = ctx.threads.threads.get_mut(&MAIN)?;
+            if main.state != State::Idle {
+                return None;
+            }
+            main.state = State::Runnable;
+            return Some(MAIN);
+        }
+        1 => runnable[0],
+        n => runnable[ctx.rng.random_range(0..n)],
+    };
+    Some(next)
+}
+
+/// Block the current thread until it is runnable again, running other threads meanwhile.
+///
+/// Returns whether the thread stopped waiting because its deadline passed.
+fn block(state: State) -> bool {
+    let next = context::with(|ctx| {
+        let thread = ctx.threads.current();
+        thread.state = state;
+        thread.timed_out = false;
+        pick(ctx)
+    });
+
+    switch(next);
+    let (deadlocked, timed_out) =
+        context::with(|ctx| (ctx.threads.deadlocked, ctx.threads.current().timed_out));
+    if deadlocked {
+        // The blocked call can't return, and this is reached from patches called from C.
+        cli::fail("all threads are blocked, the simulation can't make progress");
+    }
+    timed_out
+}
+
+/// Hand the baton to another thread, returning when the current thread gets it back.
+fn switch(next: usize) {
+    let (baton, next_baton, host) = context::with(|ctx| {
+        let current = ctx.threads.current;
+        ctx.threads.current = next;
+        let baton = ctx.threads.threads[&current].baton;
+        (baton, ctx.threads.threads[&next].baton, ctx.host.clone())
+    });
+    if std::ptr::eq(baton, next_baton) {
+        return;
+    }
+
+    if host.is_some() {
+        context::exit_host();
+    }
+    next_baton.pass();
+    baton.wait();
+    if let Some(host) = host {
+        context::enter_host(&host);
+    }
+}
+
+/// Let other threads run until they all block. Called by the main thread between steps.
+pub(crate) fn run() {
+    if context::with(|ctx| ctx.threads.is_active()) {
+        block(State::Idle);
+    }
+}
+
+/// Let the scheduler pick a thread to run, possibly the current one.
+pub(crate) fn yield_now() {
+    block(State::Runnable);
+}
+
+/// Block the current thread for a duration of simulated time.
+pub(crate) fn sleep(duration: Duration) {
+    let deadline = context::with(|ctx| ctx.monotonic()) + duration;
+    block(State::Waiting {
+        futex: None,
+        deadline: Some(deadline),
+    });
+}
+
+/// Block the current thread until a futex word is woken, or until a deadline on the current
+/// host's monotonic clock.
+///
+/// Returns whether the deadline passed.
+pub(crate) fn futex_wait(futex: usize, deadline: Option<Duration>) -> bool {
+    block(State::Waiting {
+        futex: Some(futex),
+        deadline,
+    })
+}
+
+/// Wake up to `count` threads waiting on a futex word, returning how many were woken.
+///
+/// Waking is a synchronization point, so the woken threads may run right away.
+pub(crate) fn futex_wake(futex: usize, count: usize) -> usize {
+    let woken = context::with(|ctx| ctx.threads.wake_futex(futex, count));
+    if woken > 0 {
+        yield_now();
+    }
+    woken
+}
+
+/// Wait for a green thread to finish, returning the value its start routine returned.
+///
+/// Returns `None` if the thread is not a green thread.
+pub(crate) fn join(pthread: pthread_t) -> Option<*mut c_void> {
+    let id = context::with(|ctx| ctx.threads.find(pthread))?;
+    if context::with(|ctx| ctx.threads.threads[&id].state != State::Finished) {
+        block(State::Joining(id));
+    }
+    let thread = context::with(|ctx| ctx.threads.threads.remove(&id)).unwrap();
+    Some(thread.ret as *mut c_void)
+}
+
+/// Stop scheduling the threads of a host whose process exited.
+pub(crate) fn kill(host: &str) {
+    context::with(|ctx| {
+        for thread in ctx.threads.threads.values_mut() {
+            if thread.host.as_deref() == Some(host) {
+                thread.state = State::Dead;
+            }
+        }
+    });
+}
+
+/// Start a green thread with libc's `pthread_create`.
+pub(crate) fn spawn(
+    native: *mut pthread_t,
+    attr: *const pthread_attr_t,
+    f: StartRoutine,
+    arg: *mut c_void,
+    real: unsafe extern "C" fn(
+        *mut pthread_t,
+        *const pthread_attr_t,
+        StartRoutine,
+        *mut c_void,
+    ) -> c_int,
+) -> c_int {
+    let id = context::with(|ctx| {
+        let scheduler = &mut ctx.threads;
+        if scheduler.threads.is_empty() {
+            scheduler.threads.insert(MAIN, Thread::new(None));
+        }
+        let id = scheduler.threads.last_key_value().unwrap().0 + 1;
+        let mut thread = Thread::new(ctx.host.clone());
+        // Not runnable before libc created it.
+        thread.state = State::Finished;
+        scheduler.threads.insert(id, thread);
+        id
+    });
+
+    let baton = context::with(|ctx| ctx.threads.threads[&id].baton);
+    let start = Box::into_raw(Box::new(Start { f, arg, id, baton }));
+    let ret = unsafe { real(native, attr, start_green, start.cast()) };
+    if ret != 0 {
+        drop(unsafe { Box::from_raw(start) });
+        context::with(|ctx| ctx.threads.threads.remove(&id));
+        return ret;
+    }
+
+    context::with(|ctx| {
+        let thread = ctx.threads.threads.get_mut(&id).unwrap();
+        thread.pthread = Some(unsafe { *native });
+        thread.state = State::Runnable;
+    });
+    0
+}
+
+struct Start {
+    f: StartRoutine,
+    arg: *mut c_void,
+    id: usize,
+    baton: &'static Baton,
+}
+
+extern "C" fn start_green(start: *mut c_void) -> *mut c_void {
+    // Nothing may run alongside the thread holding the baton, not even freeing `start`.
+    let baton = unsafe { (*start.cast::<Start>()).baton };
+    context::set_green();
+    baton.wait();
+
+    let Start { f, arg, id, .. } = *unsafe { Box::from_raw(start.cast::<Start>()) };
+    let host = context::with(|ctx| ctx.threads.threads[&id].host.clone());
+    if let Some(host) = &host {
+        context::enter_host(host);
+    }
+
+    let ret = f(arg);
+
+    let next = context::with(|ctx| {
+        let thread = ctx.threads.current();
+        thread.state = State::Finished;
+        thread.ret = ret as usize;
+        pick(ctx)
+    });
+    if host.is_some() {
+        context::exit_host();
+    }
+    let next_baton = context::with(|ctx| {
+        ctx.threads.current = next;
+        ctx.threads.threads[&next].baton
+    });
+    next_baton.pass();
+
+    // Exiting would run thread-local destructors alongside the next thread, so the thread is
+    // parked for good instead.
+    loop {
+        baton.wait();
+    }
+}
+
+impl Thread {
+    fn new(host: Option<String>) -> Self {
+        Self {
+            pthread: None,
+            host,
+            state: State::Runnable,
+            timed_out: false,
+            ret: 0,
+            baton: Box::leak(Box::new(Baton::new())),
+        }
+    }
+}
+
+/// Permission for a thread to run.
+///
+/// Built on libc's mutexes and condition variables, which don't go through the patched
+/// `syscall`.
+struct Baton {
+    lock: UnsafeCell<pthread_mutex_t>,
+    cond: UnsafeCell<pthread_cond_t>,
+    passed: UnsafeCell<bool>,
+}
+
+unsafe impl Sync for Baton {}
+
+impl Baton {
+    fn new() -> Self {
+        Self {
+            lock: UnsafeCell::new(libc::PTHREAD_MUTEX_INITIALIZER),
+            cond: UnsafeCell::new(libc::PTHREAD_COND_INITIALIZER),
+            passed: UnsafeCell::new(false),
+        }
+    }
+
+    fn pass(&self) {
+        unsafe {
+            libc::pthread_mutex_lock(self.lock.get());
+            *self.passed.get() = true;
+            libc::pthread_cond_signal(self.cond.get());
+            libc::pthread_mutex_unlock(self.lock.get());
+        }
+    }
+
+    fn wait(&self) {
+        unsafe {
+            libc::pthread_mutex_lock(self.lock.get());
+            while !*self.passed.get() {
+                libc::pthread_cond_wait(self.cond.get(), self.lock.get());
+            }
+            *self.passed.get() = false;
+            libc::pthread_mutex_unlock(self.lock.get());
+        }
+    }
+}
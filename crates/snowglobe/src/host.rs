@@ -0,0 +1,173 @@
+//! Tracking of the simulated host that is running.
+//!
+//! Turmoil enters a `node` span while it runs a host, and exposes no other hook into its
+//! scheduling. To not depend on the subscriber the program or the bundle installs, [`track`] makes
+//! a dispatcher of its own the default while turmoil steps. It consumes the `node` spans, entering
+//! and exiting their host in the simulation context, and forwards everything else to the
+//! dispatcher that was the default before.
+
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::LazyLock;
+
+use tracing::field::{Field, Visit};
+use tracing::level_filters::LevelFilter;
+use tracing::span::{Attributes, Id, Record};
+use tracing::subscriber::Interest;
+use tracing::{Dispatch, Event, Metadata, Subscriber, dispatcher};
+use tracing_core::span::Current;
+
+use crate::context;
+
+/// Ids of `node` spans have this bit set, so they don't collide with the forwarded spans' ids.
+const NODE_ID: u64 = 1 << 63;
+
+static TRACKER: LazyLock<Dispatch> = LazyLock::new(|| Dispatch::new(Tracker));
+
+thread_local! {
+    /// Dispatcher that everything but `node` spans is forwarded to.
+    static OUTER: RefCell<Dispatch> = RefCell::new(Dispatch::none());
+    /// Open `node` spans, with their host names and reference counts.
+    static NODES: RefCell<BTreeMap<u64, (String, usize)>> = const { RefCell::new(BTreeMap::new()) };
+    static NEXT_NODE: Cell<u64> = const { Cell::new(0) };
+}
+
+/// Run `f`, tracking the hosts turmoil runs in it.
+pub(crate) fn track<R>(f: impl FnOnce() -> R) -> R {
+    let outer = dispatcher::get_default(Dispatch::clone);
+    let outer = OUTER.replace(outer);
+    let ret = dispatcher::with_default(&TRACKER, f);
+    OUTER.set(outer);
+    ret
+}
+
+fn is_node(meta: &Metadata<'_>) -> bool {
+    meta.name() == "node" && meta.target().starts_with("turmoil")
+}
+
+fn node(id: &Id) -> Option<u64> {
+    let id = id.into_u64();
+    (id & NODE_ID != 0).then_some(id)
+}
+
+fn outer<R>(f: impl FnOnce(&Dispatch) -> R) -> R {
+    OUTER.with_borrow(f)
+}
+
+struct Tracker;
+
+impl Subscriber for Tracker {
+    fn register_callsite(&self, meta: &'static Metadata<'static>) -> Interest {
+        // The dispatcher forwarded to may change, so ask it every time.
+        if is_node(meta) {
+            Interest::always()
+        } else {
+            Interest::sometimes()
+        }
+    }
+
+    fn enabled(&self, meta: &Metadata<'_>) -> bool {
+        is_node(meta) || outer(|outer| outer.enabled(meta))
+    }
+
+    fn max_level_hint(&self) -> Option<LevelFilter> {
+        None
+    }
+
+    fn new_span(&self, attrs: &Attributes<'_>) -> Id {
+        if !is_node(attrs.metadata()) {
+            return outer(|outer| outer.new_span(attrs));
+        }
+
+        let mut name = NameVisitor(String::new());
+        attrs.record(&mut name);
+        let id = NEXT_NODE.get();
+        NEXT_NODE.set(id + 1);
+        let id = id | NODE_ID;
+        NODES.with_borrow_mut(|nodes| nodes.insert(id, (name.0, 1)));
+        Id::from_u64(id)
+    }
+
+    fn record(&self, span: &Id, values: &Record<'_>) {
+        if node(span).is_none() {
+            outer(|outer| outer.record(span, values));
+        }
+    }
+
+    fn record_follows_from(&self, span: &Id, follows: &Id) {
+        if node(span).is_none() && node(follows).is_none() {
+            outer(|outer| outer.record_follows_from(span, follows));
+        }
+    }
+
+    fn event(&self, event: &Event<'_>) {
+        outer(|outer| outer.event(event));
+    }
+
+    fn enter(&self, span: &Id) {
+        let Some(id) = node(span) else {
+            return outer(|outer| outer.enter(span));
+        };
+        if let Some(name) = NODES.with_borrow(|nodes| nodes.get(&id).map(|(name, _)| name.clone()))
+        {
+            context::enter_host(&name);
+        }
+    }
+
+    fn exit(&self, span: &Id) {
+        if node(span).is_none() {
+            return outer(|outer| outer.exit(span));
+        }
+        context::exit_host();
+    }
+
+    fn clone_span(&self, span: &Id) -> Id {
+        let Some(id) = node(span) else {
+            return outer(|outer| outer.clone_span(span));
+        };
+        NODES.with_borrow_mut(|nodes| {
+            if let Some((_, refs)) = nodes.get_mut(&id) {
+                *refs += 1;
+            }
+        });
+        span.clone()
+    }
+
+    fn try_close(&self, span: Id) -> bool {
+        let Some(id) = node(&span) else {
+            return outer(|outer| outer.try_close(span));
+        };
+        NODES.with_borrow_mut(|nodes| {
+            let Some((_, refs)) = nodes.get_mut(&id) else {
+                return false;
+            };
+            *refs -= 1;
+            if *refs > 0 {
+                return false;
+            }
+            nodes.remove(&id);
+            true
+        })
+    }
+
+    fn current_span(&self) -> Current {
+        outer(|outer| outer.current_span())
+    }
+}
+
+struct NameVisitor(String);
+
+impl Visit for NameVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "name" {
+            self.0 = value.into();
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "name" {
+            self.0 = format!("{value:?}");
+        }
+    }
+}
@@ -1,12 +1,27 @@
 mod alloc;
+mod buggify;
 mod cli;
+mod clock;
 mod context;
+mod env;
 mod error;
+mod event;
+mod green;
+mod host;
+mod log;
+mod nemesis;
 mod patch;
+mod property;
+mod seccomp;
 mod sim;
+mod tap;
+mod vfs;
 
 pub use crate::cli::{__private, main};
+pub use crate::clock::Clock;
 pub use crate::error::{Error, Result};
-pub use crate::sim::Sim;
+pub use crate::nemesis::{Fault, Nemesis, NemesisBuilder};
+pub use crate::sim::{Disk, InvariantResult, Link, Sim};
 
 pub use snowglobe_macros::scene;
+pub use snowglobe_proto::{MessageEvent, MessageEventKind};
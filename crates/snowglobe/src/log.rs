@@ -0,0 +1,131 @@
+//! Log output of scene bundles.
+//!
+//! Every log line is annotated with the simulated time and the name of the simulated host that
+//! emitted it, as tracked in the simulation context.
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde_json::{Map, Value};
+use tracing::field::{Field, Visit};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields};
+use tracing_subscriber::layer::{Layer, SubscriberExt as _};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::SubscriberInitExt as _;
+
+use crate::{context, tap};
+
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            _ => Err(format!("unknown log format: {s}")),
+        }
+    }
+}
+
+pub(crate) fn init(format: LogFormat) {
+    use tracing::level_filters::LevelFilter;
+    use tracing_subscriber::EnvFilter;
+
+    let env_filter = EnvFilter::builder()
+        .with_default_directive(LevelFilter::INFO.into())
+        .from_env_lossy();
+
+    let fmt_layer = tracing_subscriber::fmt::layer()
+        .with_writer(std::io::stderr)
+        .with_ansi(false)
+        .event_format(format);
+
+    // Filter per layer, so the tap sees turmoil's message traces regardless of the log level.
+    tracing_subscriber::registry()
+        .with(tap::layer())
+        .with(fmt_layer.with_filter(env_filter))
+        .init();
+}
+
+impl<S, N> FormatEvent<S, N> for LogFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let (time, host) = context::with(|ctx| (ctx.elapsed, ctx.host.clone()));
+        let meta = event.metadata();
+
+        match self {
+            Self::Text => {
+                let host = host.as_deref().unwrap_or("-");
+                write!(
+                    writer,
+                    "{:>12.6}s {:>5} {host} {}: ",
+                    time.as_secs_f64(),
+                    meta.level(),
+                    meta.target(),
+                )?;
+                ctx.field_format().format_fields(writer.by_ref(), event)?;
+            }
+            Self::Json => {
+                let mut fields = JsonVisitor(Map::new());
+                event.record(&mut fields);
+
+                let mut line = Map::new();
+                line.insert("sim_time".into(), time.as_secs_f64().into());
+                line.insert("host".into(), host.into());
+                line.insert("level".into(), meta.level().as_str().into());
+                line.insert("target".into(), meta.target().into());
+                line.insert("fields".into(), fields.0.into());
+
+                write!(writer, "{}", Value::Object(line))?;
+            }
+        }
+
+        writeln!(writer)
+    }
+}
+
+struct JsonVisitor(Map<String, Value>);
+
+impl Visit for JsonVisitor {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0
+            .insert(field.name().into(), format!("{value:?}").into());
+    }
+}
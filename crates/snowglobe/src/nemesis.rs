@@ -0,0 +1,204 @@
+//! Seeded fault schedules.
+
+use std::time::Duration;
+
+use rand::Rng;
+use rand::seq::IndexedRandom;
+
+use crate::{Sim, context};
+
+/// A kind of fault the nemesis can inject.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fault {
+    /// Crash a host, restarting it when the fault is healed.
+    Crash,
+    /// Partition two hosts, repairing the link when the fault is healed.
+    Partition,
+    /// Hold messages between two hosts, releasing them when the fault is healed.
+    Hold,
+}
+
+/// A fault schedule that injects and heals faults at random simulated times.
+///
+/// All random choices are drawn from the simulation RNG, so a nemesis produces the same fault
+/// pattern for the same seed.
+pub struct Nemesis {
+    hosts: Vec<String>,
+    faults: Vec<Fault>,
+    min_interval: Duration,
+    max_interval: Duration,
+    stop_after: Option<Duration>,
+
+    next_at: Option<Duration>,
+    active: Vec<ActiveFault>,
+    stopped: bool,
+}
+
+impl Nemesis {
+    pub fn builder() -> NemesisBuilder {
+        NemesisBuilder::new()
+    }
+
+    /// Inject or heal faults that are due at the current simulated time.
+    pub(crate) fn tick(&mut self, sim: &mut Sim) {
+        if self.stopped {
+            return;
+        }
+
+        let now = sim.elapsed();
+        if self.stop_after.is_some_and(|t| now >= t) {
+            while let Some(fault) = self.active.pop() {
+                fault.heal(sim);
+            }
+            self.stopped = true;
+            return;
+        }
+
+        let next_at = match self.next_at {
+            Some(t) => t,
+            None => *self.next_at.insert(now + self.interval()),
+        };
+        if now < next_at {
+            return;
+        }
+
+        // Heal and inject with equal probability, as long as there is something to heal.
+        let heal = !self.active.is_empty() && context::with(|ctx| ctx.rng.random_bool(0.5));
+        if heal {
+            let idx = context::with(|ctx| ctx.rng.random_range(0..self.active.len()));
+            let fault = self.active.swap_remove(idx);
+            fault.heal(sim);
+        } else {
+            self.inject(sim);
+        }
+
+        self.next_at = Some(now + self.interval());
+    }
+
+    fn inject(&mut self, sim: &mut Sim) {
+        let Some(&fault) = context::with(|ctx| self.faults.choose(&mut ctx.rng)) else {
+            return;
+        };
+
+        let active = match fault {
+            Fault::Crash => {
+                let up: Vec<_> = self
+                    .hosts
+                    .iter()
+                    .filter(|h| !self.active.iter().any(|f| f.is_crash_of(h)))
+                    .collect();
+                let Some(&host) = context::with(|ctx| up.choose(&mut ctx.rng)) else {
+                    return;
+                };
+                sim.crash(&**host);
+                ActiveFault::Crash(host.clone())
+            }
+            Fault::Partition | Fault::Hold => {
+                let pair: Vec<_> =
+                    context::with(|ctx| self.hosts.choose_multiple(&mut ctx.rng, 2).collect());
+                let [a, b] = pair[..] else {
+                    return;
+                };
+                if fault == Fault::Partition {
+                    sim.partition(&**a, &**b);
+                    ActiveFault::Partition(a.clone(), b.clone())
+                } else {
+                    sim.hold(&**a, &**b);
+                    ActiveFault::Hold(a.clone(), b.clone())
+                }
+            }
+        };
+
+        self.active.push(active);
+    }
+
+    fn interval(&self) -> Duration {
+        context::with(|ctx| ctx.rng.random_range(self.min_interval..=self.max_interval))
+    }
+}
+
+pub struct NemesisBuilder {
+    hosts: Vec<String>,
+    faults: Vec<Fault>,
+    min_interval: Duration,
+    max_interval: Duration,
+    stop_after: Option<Duration>,
+}
+
+impl NemesisBuilder {
+    fn new() -> Self {
+        Self {
+            hosts: Vec::new(),
+            faults: vec![Fault::Crash, Fault::Partition, Fault::Hold],
+            min_interval: Duration::from_secs(1),
+            max_interval: Duration::from_secs(5),
+            stop_after: None,
+        }
+    }
+
+    /// Set the hosts the nemesis targets.
+    ///
+    /// Hosts targeted by [`Fault::Crash`] must have been registered with [`Sim::host`].
+    pub fn hosts<I, S>(mut self, hosts: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.hosts = hosts.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Set the kinds of faults to inject (default: all).
+    pub fn faults(mut self, faults: impl IntoIterator<Item = Fault>) -> Self {
+        self.faults = faults.into_iter().collect();
+        self
+    }
+
+    /// Set the range of simulated time between two nemesis actions (default: 1s to 5s).
+    pub fn interval(mut self, min: Duration, max: Duration) -> Self {
+        assert!(min <= max, "invalid nemesis interval");
+        self.min_interval = min;
+        self.max_interval = max;
+        self
+    }
+
+    /// Heal all faults and stop the nemesis once the given simulated time has elapsed.
+    pub fn stop_after(mut self, duration: Duration) -> Self {
+        self.stop_after = Some(duration);
+        self
+    }
+
+    pub fn build(self) -> Nemesis {
+        Nemesis {
+            hosts: self.hosts,
+            faults: self.faults,
+            min_interval: self.min_interval,
+            max_interval: self.max_interval,
+            stop_after: self.stop_after,
+            next_at: None,
+            active: Vec::new(),
+            stopped: false,
+        }
+    }
+}
+
+/// A fault injected by the nemesis that has not been healed yet.
+enum ActiveFault {
+    Crash(String),
+    Partition(String, String),
+    Hold(String, String),
+}
+
+impl ActiveFault {
+    fn is_crash_of(&self, host: &str) -> bool {
+        matches!(self, Self::Crash(h) if h == host)
+    }
+
+    fn heal(self, sim: &mut Sim) {
+        match self {
+            Self::Crash(host) => sim.bounce(&*host),
+            Self::Partition(a, b) => sim.repair(&*a, &*b),
+            Self::Hold(a, b) => sim.release(&*a, &*b),
+        }
+    }
+}
@@ -0,0 +1,77 @@
+//! Changes to the environment, see [`crate::env`].
+//!
+//! These don't touch the context, so they are not defined through [`patch!`](super::patch).
+
+use std::ffi::{CStr, CString};
+
+use libc::{c_char, c_int};
+
+use super::set_errno;
+use crate::env;
+
+/// Check that a variable name is valid for `setenv` and `unsetenv`.
+unsafe fn valid_name<'a>(name: *const c_char) -> Option<&'a [u8]> {
+    if name.is_null() {
+        return None;
+    }
+    let name = unsafe { CStr::from_ptr(name) }.to_bytes();
+    (!name.is_empty() && !name.contains(&b'=')).then_some(name)
+}
+
+// https://man7.org/linux/man-pages/man3/setenv.3.html
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn setenv(
+    name: *const c_char,
+    value: *const c_char,
+    overwrite: c_int,
+) -> c_int {
+    let Some(name) = (unsafe { valid_name(name) }) else {
+        set_errno(libc::EINVAL);
+        return -1;
+    };
+    if value.is_null() {
+        set_errno(libc::EINVAL);
+        return -1;
+    }
+
+    let value = unsafe { CStr::from_ptr(value) }.to_bytes();
+    let entry = CString::new([name, b"=", value].concat())
+        .unwrap()
+        .into_raw();
+    if !env::set(entry, overwrite != 0) {
+        drop(unsafe { CString::from_raw(entry) });
+    }
+    0
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn unsetenv(name: *const c_char) -> c_int {
+    let Some(name) = (unsafe { valid_name(name) }) else {
+        set_errno(libc::EINVAL);
+        return -1;
+    };
+
+    env::remove(name);
+    0
+}
+
+// https://man7.org/linux/man-pages/man3/putenv.3.html
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn putenv(string: *mut c_char) -> c_int {
+    let entry = unsafe { CStr::from_ptr(string) }.to_bytes();
+    match entry.contains(&b'=') {
+        true => {
+            env::set(string, true);
+        }
+        // As in glibc, an entry without a value removes the variable.
+        false => env::remove(entry),
+    }
+    0
+}
+
+// https://man7.org/linux/man-pages/man3/clearenv.3.html
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn clearenv() -> c_int {
+    env::clear();
+    0
+}
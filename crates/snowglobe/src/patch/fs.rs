@@ -0,0 +1,1282 @@
+//! Filesystem calls, redirected to the in-memory filesystem of the current host.
+//!
+//! Path-based calls made while a simulated host is running go to that host's filesystem, as do
+//! all calls on virtual file descriptors. Everything else, like writes to stdout or files opened
+//! by the scene bundle itself, is passed on to libc, see [`lookup_real`].
+//!
+//! Opening `/dev/random` or `/dev/urandom` gives a virtual device whose reads draw from the
+//! simulation RNG, whether or not a host is running. Apart from these devices, a host sees none of
+//! the real filesystem: paths like `/etc`, `/proc` or `/dev/null` don't exist unless the host
+//! creates them.
+//!
+//! Only calls to the functions libc exports are redirected, not calls libc makes internally. In
+//! particular, glibc's `fopen` and the rest of its stdio open files through internal calls, so they
+//! use the real filesystem even while a host is running.
+
+use std::ffi::CStr;
+use std::mem;
+use std::sync::atomic::AtomicUsize;
+use std::time::Duration;
+
+use libc::{
+    DIR, EBADF, c_char, c_int, c_long, c_uint, c_void, iovec, mode_t, off_t, size_t, ssize_t,
+};
+use rand::Rng;
+use rand::rngs::SmallRng;
+use tracing::info;
+
+use super::{lookup_real, set_errno};
+use crate::context;
+use crate::vfs::{
+    Base, Device, Dirent, DiskFault, FsResult, HostFs, Inode, Io, OpenOptions, Vfs, is_device_fd,
+    is_virtual_fd,
+};
+
+/// Define a patched filesystem call.
+///
+/// Inside the body, `$real` is a closure that passes the call on to libc.
+///
+/// A C-variadic call takes its variadic argument after `...`. It is read whether the caller
+/// passed it or not, so it is meaningless unless the other arguments say it is there (`O_CREAT`
+/// for `open`, an argument-taking command for `fcntl`). Variadic arguments are passed like the
+/// others, except on Apple's arm64 ABI, where they are on the stack: there the patch takes the
+/// eight argument registers before it, to read the first stack slot.
+macro_rules! fs_patch {
+    (
+        fn $name:ident($( $argname:ident : $argty:ty, )+ ...$vararg:ident : $varty:ty) -> $ret:ty
+        | $real:ident | $logic:block
+    ) => {
+        #[cfg(not(all(target_vendor = "apple", target_arch = "aarch64")))]
+        #[unsafe(no_mangle)]
+        pub unsafe extern "C" fn $name($( $argname: $argty, )+ vararg: c_long) -> $ret {
+            fs_patch!(@variadic vararg => $vararg: $varty;
+                fn $name($( $argname: $argty, )+) -> $ret |$real| $logic)
+        }
+
+        #[cfg(all(target_vendor = "apple", target_arch = "aarch64"))]
+        #[unsafe(no_mangle)]
+        #[allow(clippy::too_many_arguments)]
+        pub unsafe extern "C" fn $name(
+            x0: usize,
+            x1: usize,
+            x2: usize,
+            x3: usize,
+            x4: usize,
+            x5: usize,
+            x6: usize,
+            x7: usize,
+            vararg: c_long,
+        ) -> $ret {
+            let mut regs = [x0, x1, x2, x3, x4, x5, x6, x7].into_iter();
+            $( let $argname = regs.next().unwrap() as $argty; )+
+            fs_patch!(@variadic vararg => $vararg: $varty;
+                fn $name($( $argname: $argty, )+) -> $ret |$real| $logic)
+        }
+    };
+    (
+        @variadic $raw:ident => $vararg:ident : $varty:ty;
+        fn $name:ident($( $argname:ident : $argty:ty, )+) -> $ret:ty |$real:ident| $logic:block
+    ) => {{
+        #[allow(unused)]
+        let $real = || -> $ret {
+            static ADDR: AtomicUsize = AtomicUsize::new(0);
+            let f: unsafe extern "C" fn($( $argty, )+ ...) -> $ret =
+                unsafe { lookup_real(&ADDR, concat!(stringify!($name), "\0")) };
+            unsafe { f($( $argname, )+ $raw) }
+        };
+        #[allow(unused)]
+        let $vararg = $raw as $varty;
+        $logic
+    }};
+    (
+        fn $name:ident($( $argname:ident : $argty:ty ),* $(,)?) -> $ret:ty
+        | $real:ident | $logic:block
+    ) => {
+        #[unsafe(no_mangle)]
+        pub unsafe extern "C" fn $name($( $argname: $argty ),*) -> $ret {
+            #[allow(unused)]
+            let $real = || -> $ret {
+                static ADDR: AtomicUsize = AtomicUsize::new(0);
+                let f: unsafe extern "C" fn($( $argty ),*) -> $ret =
+                    unsafe { lookup_real(&ADDR, concat!(stringify!($name), "\0")) };
+                unsafe { f($( $argname ),*) }
+            };
+            $logic
+        }
+    };
+}
+
+/// Whether a simulated host is currently running.
+fn in_host() -> bool {
+    context::with(|ctx| ctx.host.is_some())
+}
+
+/// Decide whether a path-based call goes to the virtual filesystem, and where to resolve the
+/// path from.
+///
+/// While a host is running, all paths go to its filesystem, including those of system files.
+fn route(dirfd: c_int) -> Option<Base> {
+    if is_virtual_fd(dirfd) {
+        Some(Base::Fd(dirfd))
+    } else if dirfd == libc::AT_FDCWD && in_host() {
+        Some(Base::Cwd)
+    } else {
+        None
+    }
+}
+
+/// Run `f` on the filesystem of the current host, passing it the host's wall-clock time.
+fn with_fs<R>(f: impl FnOnce(&mut HostFs, Duration) -> FsResult<R>) -> FsResult<R> {
+    context::with(|ctx| {
+        let now = ctx.realtime();
+        let host = ctx.host.as_deref().ok_or(EBADF)?;
+        f(ctx.vfs.host(host), now)
+    })
+}
+
+/// Inject the configured disk latency and faults into an I/O operation on a virtual file.
+///
+/// The latency advances the clock of the current host. Returns the number of bytes the operation
+/// may transfer out of `len`, or the `errno` it fails with. Injected faults are logged in the
+/// span of the current host, which records its name and the simulated time.
+fn inject(io: Io, fd: c_int, len: usize) -> FsResult<usize> {
+    let fault = context::with(|ctx| -> FsResult<_> {
+        let host = ctx.host.as_deref().ok_or(EBADF)?;
+        let fs = ctx.vfs.host(host);
+        fs.check_fd(fd)?;
+        let faults = fs.faults;
+
+        ctx.advance_host_clock(faults.latency);
+        Ok(faults.draw(io, len, &mut ctx.rng))
+    })?;
+
+    // Logging is done without the context borrowed, as writing the log calls into libc.
+    match fault {
+        None => Ok(len),
+        Some(fault) => {
+            info!(?io, fd, ?fault, "disk fault injected");
+            match fault {
+                DiskFault::Error(errno) => Err(errno),
+                DiskFault::Short(n) => Ok(n),
+            }
+        }
+    }
+}
+
+/// Convert a result to a libc return value, setting `errno` on error.
+fn ret<T>(result: FsResult<T>, error: T) -> T {
+    result.unwrap_or_else(|errno| {
+        set_errno(errno);
+        error
+    })
+}
+
+unsafe fn path<'a>(path: *const c_char) -> &'a [u8] {
+    unsafe { CStr::from_ptr(path) }.to_bytes()
+}
+
+unsafe fn buf<'a>(buf: *const c_void, len: size_t) -> &'a [u8] {
+    match len {
+        0 => &[],
+        _ => unsafe { std::slice::from_raw_parts(buf.cast(), len) },
+    }
+}
+
+unsafe fn buf_mut<'a>(buf: *mut c_void, len: size_t) -> &'a mut [u8] {
+    match len {
+        0 => &mut [],
+        _ => unsafe { std::slice::from_raw_parts_mut(buf.cast(), len) },
+    }
+}
+
+fn file_type(inode: &Inode) -> mode_t {
+    match inode.is_dir() {
+        true => libc::S_IFDIR,
+        false => libc::S_IFREG,
+    }
+}
+
+/// Fill a `stat` buffer, or any of its variants, from an inode.
+macro_rules! fill_stat {
+    ($buf:expr, $inode:expr) => {{
+        let (buf, inode) = ($buf, $inode);
+        unsafe {
+            *buf = mem::zeroed();
+            (*buf).st_ino = inode.ino as _;
+            (*buf).st_mode = (file_type(inode) | inode.mode) as _;
+            (*buf).st_nlink = inode.nlink as _;
+            (*buf).st_size = inode.size() as _;
+            (*buf).st_blksize = 4096;
+            (*buf).st_blocks = inode.size().div_ceil(512) as _;
+            (*buf).st_atime = inode.mtime.as_secs() as _;
+            (*buf).st_atime_nsec = inode.mtime.subsec_nanos() as _;
+            (*buf).st_mtime = inode.mtime.as_secs() as _;
+            (*buf).st_mtime_nsec = inode.mtime.subsec_nanos() as _;
+            (*buf).st_ctime = inode.mtime.as_secs() as _;
+            (*buf).st_ctime_nsec = inode.mtime.subsec_nanos() as _;
+        }
+    }};
+}
+
+// Opening and closing
+
+/// Open a path relative to `dirfd`, in the virtual filesystem or by passing the call on.
+fn open_impl(
+    dirfd: c_int,
+    pathname: *const c_char,
+    flags: c_int,
+    mode: mode_t,
+    real: impl FnOnce() -> c_int,
+) -> c_int {
+    let path = unsafe { path(pathname) };
+    if let Some(device) = Device::from_path(path) {
+        return ret(open_device(device, flags), -1);
+    }
+
+    let Some(base) = route(dirfd) else {
+        return real();
+    };
+    let opts = OpenOptions::from_flags(flags);
+    let result = with_fs(|fs, now| fs.open(base, path, opts, mode, now));
+    ret(result, -1)
+}
+
+// https://man7.org/linux/man-pages/man2/open.2.html
+fs_patch! {
+    fn open(pathname: *const c_char, flags: c_int, ...mode: mode_t) -> c_int
+    |real| {
+        open_impl(libc::AT_FDCWD, pathname, flags, mode, real)
+    }
+}
+
+#[cfg(target_os = "linux")]
+fs_patch! {
+    fn open64(pathname: *const c_char, flags: c_int, ...mode: mode_t) -> c_int
+    |real| {
+        open_impl(libc::AT_FDCWD, pathname, flags, mode, real)
+    }
+}
+
+// https://man7.org/linux/man-pages/man2/openat.2.html
+fs_patch! {
+    fn openat(dirfd: c_int, pathname: *const c_char, flags: c_int, ...mode: mode_t) -> c_int
+    |real| {
+        open_impl(dirfd, pathname, flags, mode, real)
+    }
+}
+
+#[cfg(target_os = "linux")]
+fs_patch! {
+    fn openat64(dirfd: c_int, pathname: *const c_char, flags: c_int, ...mode: mode_t) -> c_int
+    |real| {
+        open_impl(dirfd, pathname, flags, mode, real)
+    }
+}
+
+// https://man7.org/linux/man-pages/man3/creat.3p.html
+fs_patch! {
+    fn creat(pathname: *const c_char, mode: mode_t) -> c_int
+    |real| {
+        let flags = libc::O_CREAT | libc::O_WRONLY | libc::O_TRUNC;
+        open_impl(libc::AT_FDCWD, pathname, flags, mode, real)
+    }
+}
+
+// https://man7.org/linux/man-pages/man2/close.2.html
+fs_patch! {
+    fn close(fd: c_int) -> c_int
+    |real| {
+        if !is_virtual_fd(fd) {
+            return real();
+        }
+        let result = match is_device_fd(fd) {
+            true => context::with(|ctx| ctx.vfs.close_device(fd)),
+            false => with_fs(|fs, _| fs.close(fd)),
+        };
+        ret(result.map(|()| 0), -1)
+    }
+}
+
+fn dup_impl(fd: c_int, new: Option<c_int>) -> c_int {
+    let result = match is_device_fd(fd) {
+        true => context::with(|ctx| ctx.vfs.dup_device(fd, new)),
+        false => with_fs(|fs, _| fs.dup(fd, new)),
+    };
+    ret(result, -1)
+}
+
+// https://man7.org/linux/man-pages/man2/dup.2.html
+fs_patch! {
+    fn dup(oldfd: c_int) -> c_int
+    |real| {
+        match is_virtual_fd(oldfd) {
+            true => dup_impl(oldfd, None),
+            false => real(),
+        }
+    }
+}
+
+// A virtual file can only be duplicated onto a virtual file descriptor of the same kind.
+fs_patch! {
+    fn dup2(oldfd: c_int, newfd: c_int) -> c_int
+    |real| {
+        match is_virtual_fd(oldfd) {
+            true => dup_impl(oldfd, Some(newfd)),
+            false => real(),
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fs_patch! {
+    fn dup3(oldfd: c_int, newfd: c_int, flags: c_int) -> c_int
+    |real| {
+        if !is_virtual_fd(oldfd) {
+            return real();
+        }
+        if oldfd == newfd || flags & !libc::O_CLOEXEC != 0 {
+            set_errno(libc::EINVAL);
+            return -1;
+        }
+        dup_impl(oldfd, Some(newfd))
+    }
+}
+
+fn fcntl_impl(fd: c_int, cmd: c_int) -> c_int {
+    if cmd == libc::F_DUPFD || cmd == libc::F_DUPFD_CLOEXEC {
+        return dup_impl(fd, None);
+    }
+
+    let status_flags = match is_device_fd(fd) {
+        true => context::with(|ctx| ctx.vfs.device_flags(fd)),
+        false => with_fs(|fs, _| fs.status_flags(fd)),
+    };
+    let result = status_flags.and_then(|flags| match cmd {
+        libc::F_GETFD => Ok(libc::FD_CLOEXEC),
+        libc::F_SETFD | libc::F_SETFL => Ok(0),
+        libc::F_GETFL => Ok(flags),
+        _ => Err(libc::EINVAL),
+    });
+    ret(result, -1)
+}
+
+// https://man7.org/linux/man-pages/man2/fcntl.2.html
+fs_patch! {
+    fn fcntl(fd: c_int, cmd: c_int, ...arg: c_long) -> c_int
+    |real| {
+        match is_virtual_fd(fd) {
+            true => fcntl_impl(fd, cmd),
+            false => real(),
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fs_patch! {
+    fn fcntl64(fd: c_int, cmd: c_int, ...arg: c_long) -> c_int
+    |real| {
+        match is_virtual_fd(fd) {
+            true => fcntl_impl(fd, cmd),
+            false => real(),
+        }
+    }
+}
+
+// Reading and writing
+
+// https://man7.org/linux/man-pages/man2/read.2.html
+fs_patch! {
+    fn read(fd: c_int, buffer: *mut c_void, count: size_t) -> ssize_t
+    |real| {
+        if !is_virtual_fd(fd) {
+            return real();
+        }
+        let buffer = unsafe { buf_mut(buffer, count) };
+        if is_device_fd(fd) {
+            return ret(read_device(fd, buffer).map(|n| n as ssize_t), -1);
+        }
+        let result = inject(Io::Read, fd, count).and_then(|len| {
+            with_fs(|fs, _| fs.read(fd, &mut buffer[..len]))
+        });
+        ret(result.map(|n| n as ssize_t), -1)
+    }
+}
+
+// https://man7.org/linux/man-pages/man2/write.2.html
+fs_patch! {
+    fn write(fd: c_int, buffer: *const c_void, count: size_t) -> ssize_t
+    |real| {
+        if !is_virtual_fd(fd) {
+            return real();
+        }
+        let buffer = unsafe { buf(buffer, count) };
+        if is_device_fd(fd) {
+            return ret(write_device(fd, buffer).map(|n| n as ssize_t), -1);
+        }
+        let result = inject(Io::Write, fd, count).and_then(|len| {
+            with_fs(|fs, now| fs.write(fd, &buffer[..len], now))
+        });
+        ret(result.map(|n| n as ssize_t), -1)
+    }
+}
+
+fn pread_impl(fd: c_int, buffer: *mut c_void, count: size_t, offset: off_t) -> ssize_t {
+    let buffer = unsafe { buf_mut(buffer, count) };
+    if is_device_fd(fd) {
+        return ret(read_device(fd, buffer).map(|n| n as ssize_t), -1);
+    }
+    let result = inject(Io::Read, fd, count).and_then(|len| {
+        with_fs(|fs, _| {
+            let offset = u64::try_from(offset).map_err(|_| libc::EINVAL)?;
+            fs.read_at(fd, &mut buffer[..len], offset)
+        })
+    });
+    ret(result.map(|n| n as ssize_t), -1)
+}
+
+fn pwrite_impl(fd: c_int, buffer: *const c_void, count: size_t, offset: off_t) -> ssize_t {
+    let buffer = unsafe { buf(buffer, count) };
+    if is_device_fd(fd) {
+        return ret(write_device(fd, buffer).map(|n| n as ssize_t), -1);
+    }
+    let result = inject(Io::Write, fd, count).and_then(|len| {
+        with_fs(|fs, now| {
+            let offset = u64::try_from(offset).map_err(|_| libc::EINVAL)?;
+            fs.write_at(fd, &buffer[..len], offset, now)
+        })
+    });
+    ret(result.map(|n| n as ssize_t), -1)
+}
+
+// https://man7.org/linux/man-pages/man2/pread.2.html
+fs_patch! {
+    fn pread(fd: c_int, buffer: *mut c_void, count: size_t, offset: off_t) -> ssize_t
+    |real| {
+        match is_virtual_fd(fd) {
+            true => pread_impl(fd, buffer, count, offset),
+            false => real(),
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fs_patch! {
+    fn pread64(fd: c_int, buffer: *mut c_void, count: size_t, offset: off_t) -> ssize_t
+    |real| {
+        match is_virtual_fd(fd) {
+            true => pread_impl(fd, buffer, count, offset),
+            false => real(),
+        }
+    }
+}
+
+// https://man7.org/linux/man-pages/man2/pwrite.2.html
+fs_patch! {
+    fn pwrite(fd: c_int, buffer: *const c_void, count: size_t, offset: off_t) -> ssize_t
+    |real| {
+        match is_virtual_fd(fd) {
+            true => pwrite_impl(fd, buffer, count, offset),
+            false => real(),
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fs_patch! {
+    fn pwrite64(fd: c_int, buffer: *const c_void, count: size_t, offset: off_t) -> ssize_t
+    |real| {
+        match is_virtual_fd(fd) {
+            true => pwrite_impl(fd, buffer, count, offset),
+            false => real(),
+        }
+    }
+}
+
+// https://man7.org/linux/man-pages/man2/readv.2.html
+fs_patch! {
+    fn readv(fd: c_int, iov: *const iovec, iovcnt: c_int) -> ssize_t
+    |real| {
+        if !is_virtual_fd(fd) {
+            return real();
+        }
+
+        let iovs = unsafe { std::slice::from_raw_parts(iov, iovcnt.max(0) as usize) };
+        if is_device_fd(fd) {
+            let result = iovs.iter().try_fold(0, |total, iov| {
+                Ok(total + read_device(fd, unsafe { buf_mut(iov.iov_base, iov.iov_len) })?)
+            });
+            return ret(result.map(|n: usize| n as ssize_t), -1);
+        }
+        let count = iovs.iter().map(|iov| iov.iov_len).sum();
+        let result = inject(Io::Read, fd, count).and_then(|mut remaining| {
+            with_fs(|fs, _| {
+                let mut total = 0;
+                for iov in iovs {
+                    let len = iov.iov_len.min(remaining);
+                    let n = fs.read(fd, unsafe { buf_mut(iov.iov_base, len) })?;
+                    total += n;
+                    remaining -= n;
+                    if n < iov.iov_len {
+                        break;
+                    }
+                }
+                Ok(total as ssize_t)
+            })
+        });
+        ret(result, -1)
+    }
+}
+
+// https://man7.org/linux/man-pages/man2/writev.2.html
+fs_patch! {
+    fn writev(fd: c_int, iov: *const iovec, iovcnt: c_int) -> ssize_t
+    |real| {
+        if !is_virtual_fd(fd) {
+            return real();
+        }
+
+        let iovs = unsafe { std::slice::from_raw_parts(iov, iovcnt.max(0) as usize) };
+        if is_device_fd(fd) {
+            let result = iovs.iter().try_fold(0, |total, iov| {
+                Ok(total + write_device(fd, unsafe { buf(iov.iov_base, iov.iov_len) })?)
+            });
+            return ret(result.map(|n: usize| n as ssize_t), -1);
+        }
+        let count = iovs.iter().map(|iov| iov.iov_len).sum();
+        let result = inject(Io::Write, fd, count).and_then(|mut remaining| {
+            with_fs(|fs, now| {
+                let mut total = 0;
+                for iov in iovs {
+                    let len = iov.iov_len.min(remaining);
+                    let n = fs.write(fd, unsafe { buf(iov.iov_base, len) }, now)?;
+                    total += n;
+                    remaining -= n;
+                }
+                Ok(total as ssize_t)
+            })
+        });
+        ret(result, -1)
+    }
+}
+
+fn seek_impl(fd: c_int, offset: off_t, whence: c_int) -> FsResult<u64> {
+    match is_device_fd(fd) {
+        // Seeking is allowed on random devices, but has no effect.
+        true => with_device(fd, |_, _| Ok(0)),
+        false => with_fs(|fs, _| fs.seek(fd, offset, whence)),
+    }
+}
+
+// https://man7.org/linux/man-pages/man2/lseek.2.html
+fs_patch! {
+    fn lseek(fd: c_int, offset: off_t, whence: c_int) -> off_t
+    |real| {
+        if !is_virtual_fd(fd) {
+            return real();
+        }
+        ret(seek_impl(fd, offset, whence).map(|o| o as off_t), -1)
+    }
+}
+
+#[cfg(target_os = "linux")]
+fs_patch! {
+    fn lseek64(fd: c_int, offset: off_t, whence: c_int) -> off_t
+    |real| {
+        if !is_virtual_fd(fd) {
+            return real();
+        }
+        ret(seek_impl(fd, offset, whence).map(|o| o as off_t), -1)
+    }
+}
+
+// https://man7.org/linux/man-pages/man2/ftruncate.2.html
+fs_patch! {
+    fn ftruncate(fd: c_int, length: off_t) -> c_int
+    |real| {
+        if !is_virtual_fd(fd) {
+            return real();
+        }
+        let result = with_fs(|fs, now| {
+            let length = u64::try_from(length).map_err(|_| libc::EINVAL)?;
+            fs.truncate(fd, length, now)
+        });
+        ret(result.map(|()| 0), -1)
+    }
+}
+
+#[cfg(target_os = "linux")]
+fs_patch! {
+    fn ftruncate64(fd: c_int, length: off_t) -> c_int
+    |real| {
+        if !is_virtual_fd(fd) {
+            return real();
+        }
+        let result = with_fs(|fs, now| {
+            let length = u64::try_from(length).map_err(|_| libc::EINVAL)?;
+            fs.truncate(fd, length, now)
+        });
+        ret(result.map(|()| 0), -1)
+    }
+}
+
+fn sync_impl(fd: c_int) -> FsResult<()> {
+    match is_device_fd(fd) {
+        true => with_device(fd, |_, _| Err(libc::EINVAL)),
+        false => inject(Io::Sync, fd, 0).and_then(|_| with_fs(|fs, _| fs.sync(fd))),
+    }
+}
+
+// https://man7.org/linux/man-pages/man2/fsync.2.html
+fs_patch! {
+    fn fsync(fd: c_int) -> c_int
+    |real| {
+        if !is_virtual_fd(fd) {
+            return real();
+        }
+        ret(sync_impl(fd).map(|()| 0), -1)
+    }
+}
+
+fs_patch! {
+    fn fdatasync(fd: c_int) -> c_int
+    |real| {
+        if !is_virtual_fd(fd) {
+            return real();
+        }
+        ret(sync_impl(fd).map(|()| 0), -1)
+    }
+}
+
+// https://man7.org/linux/man-pages/man2/sync.2.html
+fs_patch! {
+    fn sync() -> ()
+    |real| {
+        if !in_host() {
+            return real();
+        }
+        let _ = with_fs(|fs, _| {
+            fs.sync_all();
+            Ok(())
+        });
+    }
+}
+
+#[cfg(target_os = "linux")]
+fs_patch! {
+    fn syncfs(fd: c_int) -> c_int
+    |real| {
+        if !is_virtual_fd(fd) {
+            return real();
+        }
+        let result = with_fs(|fs, _| {
+            fs.check_fd(fd)?;
+            fs.sync_all();
+            Ok(0)
+        });
+        ret(result, -1)
+    }
+}
+
+// Devices
+
+/// Run `f` on the device open at `fd`, passing it the simulation RNG.
+fn with_device<R>(fd: c_int, f: impl FnOnce(Device, &mut SmallRng) -> FsResult<R>) -> FsResult<R> {
+    context::with(|ctx| {
+        let device = ctx.vfs.device(fd)?;
+        f(device, &mut ctx.rng)
+    })
+}
+
+fn open_device(device: Device, flags: c_int) -> FsResult<c_int> {
+    if flags & libc::O_CREAT != 0 && flags & libc::O_EXCL != 0 {
+        return Err(libc::EEXIST);
+    }
+    if flags & libc::O_DIRECTORY != 0 {
+        return Err(libc::ENOTDIR);
+    }
+
+    context::with(|ctx| {
+        let host = ctx.host.clone();
+        Ok(ctx.vfs.open_device(host, device, flags))
+    })
+}
+
+fn read_device(fd: c_int, buf: &mut [u8]) -> FsResult<usize> {
+    with_device(fd, |_, rng| {
+        rng.fill(buf);
+        Ok(buf.len())
+    })
+}
+
+/// Writing to a random device is allowed, but the data is discarded.
+fn write_device(fd: c_int, buf: &[u8]) -> FsResult<usize> {
+    with_device(fd, |_, _| Ok(buf.len()))
+}
+
+fn stat_device(device: Device, buf: *mut Stat) -> c_int {
+    unsafe {
+        *buf = mem::zeroed();
+        (*buf).st_mode = (libc::S_IFCHR | 0o666) as _;
+        (*buf).st_nlink = 1;
+        (*buf).st_rdev = device.rdev();
+        (*buf).st_blksize = 4096;
+    }
+    0
+}
+
+// Metadata
+
+#[cfg(target_os = "linux")]
+type Stat = libc::stat64;
+#[cfg(target_os = "macos")]
+type Stat = libc::stat;
+
+fn fstat_impl(fd: c_int, buf: *mut Stat) -> c_int {
+    if is_device_fd(fd) {
+        let result = with_device(fd, |device, _| Ok(stat_device(device, buf)));
+        return ret(result, -1);
+    }
+
+    let result = with_fs(|fs, _| {
+        fill_stat!(buf, fs.fstat(fd)?);
+        Ok(0)
+    });
+    ret(result, -1)
+}
+
+fn stat_impl(base: Base, pathname: *const c_char, buf: *mut Stat) -> c_int {
+    let path = unsafe { path(pathname) };
+    if let Some(device) = Device::from_path(path) {
+        return stat_device(device, buf);
+    }
+
+    let result = with_fs(|fs, _| {
+        fill_stat!(buf, fs.stat(base, path)?);
+        Ok(0)
+    });
+    ret(result, -1)
+}
+
+// https://man7.org/linux/man-pages/man2/stat.2.html
+fs_patch! {
+    fn fstat(fd: c_int, buf: *mut Stat) -> c_int
+    |real| {
+        match is_virtual_fd(fd) {
+            true => fstat_impl(fd, buf),
+            false => real(),
+        }
+    }
+}
+
+fs_patch! {
+    fn stat(pathname: *const c_char, buf: *mut Stat) -> c_int
+    |real| {
+        match route(libc::AT_FDCWD) {
+            Some(base) => stat_impl(base, pathname, buf),
+            None => real(),
+        }
+    }
+}
+
+// There are no symlinks, so `lstat` is the same as `stat`.
+fs_patch! {
+    fn lstat(pathname: *const c_char, buf: *mut Stat) -> c_int
+    |real| {
+        match route(libc::AT_FDCWD) {
+            Some(base) => stat_impl(base, pathname, buf),
+            None => real(),
+        }
+    }
+}
+
+fs_patch! {
+    fn fstatat(dirfd: c_int, pathname: *const c_char, buf: *mut Stat, flags: c_int) -> c_int
+    |real| {
+        let empty = unsafe { path(pathname) }.is_empty();
+        match route(dirfd) {
+            Some(Base::Fd(fd)) if empty && flags & libc::AT_EMPTY_PATH != 0 => fstat_impl(fd, buf),
+            Some(base) => stat_impl(base, pathname, buf),
+            None => real(),
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fs_patch! {
+    fn fstat64(fd: c_int, buf: *mut Stat) -> c_int
+    |real| {
+        match is_virtual_fd(fd) {
+            true => fstat_impl(fd, buf),
+            false => real(),
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fs_patch! {
+    fn stat64(pathname: *const c_char, buf: *mut Stat) -> c_int
+    |real| {
+        match route(libc::AT_FDCWD) {
+            Some(base) => stat_impl(base, pathname, buf),
+            None => real(),
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fs_patch! {
+    fn lstat64(pathname: *const c_char, buf: *mut Stat) -> c_int
+    |real| {
+        match route(libc::AT_FDCWD) {
+            Some(base) => stat_impl(base, pathname, buf),
+            None => real(),
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fs_patch! {
+    fn fstatat64(dirfd: c_int, pathname: *const c_char, buf: *mut Stat, flags: c_int) -> c_int
+    |real| {
+        let empty = unsafe { path(pathname) }.is_empty();
+        match route(dirfd) {
+            Some(Base::Fd(fd)) if empty && flags & libc::AT_EMPTY_PATH != 0 => fstat_impl(fd, buf),
+            Some(base) => stat_impl(base, pathname, buf),
+            None => real(),
+        }
+    }
+}
+
+// https://man7.org/linux/man-pages/man2/statx.2.html
+#[cfg(target_os = "linux")]
+fs_patch! {
+    fn statx(
+        dirfd: c_int,
+        pathname: *const c_char,
+        flags: c_int,
+        _mask: c_uint,
+        buf: *mut libc::statx,
+    ) -> c_int
+    |real| {
+        let Some(base) = route(dirfd) else {
+            return real();
+        };
+
+        let path = unsafe { path(pathname) };
+        let device = match base {
+            Base::Fd(fd) if is_device_fd(fd) && flags & libc::AT_EMPTY_PATH != 0 => {
+                with_device(fd, |device, _| Ok(Some(device)))
+            }
+            _ => Ok(Device::from_path(path)),
+        };
+        match device {
+            Ok(Some(device)) => {
+                unsafe {
+                    *buf = mem::zeroed();
+                    (*buf).stx_mask = libc::STATX_BASIC_STATS;
+                    (*buf).stx_blksize = 4096;
+                    (*buf).stx_nlink = 1;
+                    (*buf).stx_mode = (libc::S_IFCHR | 0o666) as u16;
+                    (*buf).stx_rdev_major = libc::major(device.rdev());
+                    (*buf).stx_rdev_minor = libc::minor(device.rdev());
+                }
+                return 0;
+            }
+            Ok(None) => {}
+            Err(errno) => return ret(Err(errno), -1),
+        }
+
+        let result = with_fs(|fs, _| {
+            let inode = match base {
+                Base::Fd(fd) if path.is_empty() && flags & libc::AT_EMPTY_PATH != 0 => {
+                    fs.fstat(fd)?
+                }
+                base => fs.stat(base, path)?,
+            };
+
+            let mut time: libc::statx_timestamp = unsafe { mem::zeroed() };
+            time.tv_sec = inode.mtime.as_secs() as _;
+            time.tv_nsec = inode.mtime.subsec_nanos();
+            unsafe {
+                *buf = mem::zeroed();
+                (*buf).stx_mask = libc::STATX_BASIC_STATS;
+                (*buf).stx_blksize = 4096;
+                (*buf).stx_nlink = inode.nlink;
+                (*buf).stx_mode = (file_type(inode) | inode.mode) as u16;
+                (*buf).stx_ino = inode.ino;
+                (*buf).stx_size = inode.size();
+                (*buf).stx_blocks = inode.size().div_ceil(512);
+                (*buf).stx_atime = time;
+                (*buf).stx_mtime = time;
+                (*buf).stx_ctime = time;
+            }
+            Ok(0)
+        });
+        ret(result, -1)
+    }
+}
+
+fn access_impl(base: Base, pathname: *const c_char, mode: c_int) -> c_int {
+    let path = unsafe { path(pathname) };
+    let result = match Device::from_path(path) {
+        Some(_) => Ok(0o666),
+        None => with_fs(|fs, _| Ok(fs.stat(base, path)?.mode)),
+    };
+
+    // Permissions are checked against the owner bits, as if the host's user owned every file.
+    let result = result.and_then(|perm| {
+        let checks = [
+            (libc::R_OK, 0o400),
+            (libc::W_OK, 0o200),
+            (libc::X_OK, 0o100),
+        ];
+        match checks
+            .iter()
+            .all(|(bit, owner)| mode & bit == 0 || perm & owner != 0)
+        {
+            true => Ok(0),
+            false => Err(libc::EACCES),
+        }
+    });
+    ret(result, -1)
+}
+
+// https://man7.org/linux/man-pages/man2/access.2.html
+fs_patch! {
+    fn access(pathname: *const c_char, mode: c_int) -> c_int
+    |real| {
+        match route(libc::AT_FDCWD) {
+            Some(base) => access_impl(base, pathname, mode),
+            None => real(),
+        }
+    }
+}
+
+fs_patch! {
+    fn faccessat(dirfd: c_int, pathname: *const c_char, mode: c_int, _flags: c_int) -> c_int
+    |real| {
+        match route(dirfd) {
+            Some(base) => access_impl(base, pathname, mode),
+            None => real(),
+        }
+    }
+}
+
+// https://man7.org/linux/man-pages/man2/readlink.2.html
+//
+// There are no symlinks, so this fails for every path that exists.
+fs_patch! {
+    fn readlink(pathname: *const c_char, _buf: *mut c_char, _bufsiz: size_t) -> ssize_t
+    |real| {
+        let Some(base) = route(libc::AT_FDCWD) else {
+            return real();
+        };
+        let path = unsafe { path(pathname) };
+        let result = match Device::from_path(path) {
+            Some(_) => Err(libc::EINVAL),
+            None => with_fs(|fs, _| fs.stat(base, path).and(Err(libc::EINVAL))),
+        };
+        ret(result, -1)
+    }
+}
+
+// Directories
+
+// https://man7.org/linux/man-pages/man2/mkdir.2.html
+fs_patch! {
+    fn mkdir(pathname: *const c_char, mode: mode_t) -> c_int
+    |real| {
+        let Some(base) = route(libc::AT_FDCWD) else {
+            return real();
+        };
+        let path = unsafe { path(pathname) };
+        ret(with_fs(|fs, now| fs.mkdir(base, path, mode, now)).map(|()| 0), -1)
+    }
+}
+
+fs_patch! {
+    fn mkdirat(dirfd: c_int, pathname: *const c_char, mode: mode_t) -> c_int
+    |real| {
+        let Some(base) = route(dirfd) else {
+            return real();
+        };
+        let path = unsafe { path(pathname) };
+        ret(with_fs(|fs, now| fs.mkdir(base, path, mode, now)).map(|()| 0), -1)
+    }
+}
+
+// https://man7.org/linux/man-pages/man2/rmdir.2.html
+fs_patch! {
+    fn rmdir(pathname: *const c_char) -> c_int
+    |real| {
+        let Some(base) = route(libc::AT_FDCWD) else {
+            return real();
+        };
+        let path = unsafe { path(pathname) };
+        ret(with_fs(|fs, now| fs.rmdir(base, path, now)).map(|()| 0), -1)
+    }
+}
+
+// https://man7.org/linux/man-pages/man2/unlink.2.html
+fs_patch! {
+    fn unlink(pathname: *const c_char) -> c_int
+    |real| {
+        let Some(base) = route(libc::AT_FDCWD) else {
+            return real();
+        };
+        let path = unsafe { path(pathname) };
+        ret(with_fs(|fs, now| fs.unlink(base, path, now)).map(|()| 0), -1)
+    }
+}
+
+fs_patch! {
+    fn unlinkat(dirfd: c_int, pathname: *const c_char, flags: c_int) -> c_int
+    |real| {
+        let Some(base) = route(dirfd) else {
+            return real();
+        };
+        let path = unsafe { path(pathname) };
+        let result = with_fs(|fs, now| match flags & libc::AT_REMOVEDIR {
+            0 => fs.unlink(base, path, now),
+            _ => fs.rmdir(base, path, now),
+        });
+        ret(result.map(|()| 0), -1)
+    }
+}
+
+// https://man7.org/linux/man-pages/man2/rename.2.html
+fs_patch! {
+    fn rename(oldpath: *const c_char, newpath: *const c_char) -> c_int
+    |real| {
+        let Some(base) = route(libc::AT_FDCWD) else {
+            return real();
+        };
+        let (from, to) = unsafe { (path(oldpath), path(newpath)) };
+        ret(with_fs(|fs, now| fs.rename(base, from, base, to, now)).map(|()| 0), -1)
+    }
+}
+
+fs_patch! {
+    fn renameat(
+        olddirfd: c_int,
+        oldpath: *const c_char,
+        newdirfd: c_int,
+        newpath: *const c_char,
+    ) -> c_int
+    |real| {
+        let (Some(from_base), Some(to_base)) = (route(olddirfd), route(newdirfd)) else {
+            return real();
+        };
+        let (from, to) = unsafe { (path(oldpath), path(newpath)) };
+        let result = with_fs(|fs, now| fs.rename(from_base, from, to_base, to, now));
+        ret(result.map(|()| 0), -1)
+    }
+}
+
+/// Whether a directory stream belongs to the virtual filesystem.
+///
+/// This holds outside of the host that opened the stream too, so that it is never passed on to
+/// libc.
+fn is_virtual_dir(dirp: *mut DIR) -> bool {
+    context::with(|ctx| ctx.vfs.dir_stream(dirp as usize).is_some())
+}
+
+/// Run `f` on the VFS, passing it the name of the current host.
+fn with_host_vfs<R>(f: impl FnOnce(&mut Vfs, &str) -> FsResult<R>) -> FsResult<R> {
+    context::with(|ctx| {
+        let host = ctx.host.as_deref().ok_or(EBADF)?;
+        f(&mut ctx.vfs, host)
+    })
+}
+
+// https://man7.org/linux/man-pages/man3/opendir.3.html
+fs_patch! {
+    fn opendir(name: *const c_char) -> *mut DIR
+    |real| {
+        let Some(base) = route(libc::AT_FDCWD) else {
+            return real();
+        };
+        let path = unsafe { path(name) };
+        let result = with_host_vfs(|vfs, host| vfs.opendir(host, base, path));
+        ret(result.map(|addr| addr as *mut DIR), std::ptr::null_mut())
+    }
+}
+
+fs_patch! {
+    fn fdopendir(fd: c_int) -> *mut DIR
+    |real| {
+        if !is_virtual_fd(fd) {
+            return real();
+        }
+        let result = with_host_vfs(|vfs, host| vfs.fdopendir(host, fd));
+        ret(result.map(|addr| addr as *mut DIR), std::ptr::null_mut())
+    }
+}
+
+fn readdir_impl(dirp: *mut DIR) -> *mut Dirent {
+    let result = context::with(|ctx| {
+        let stream = ctx.vfs.dir_stream(dirp as usize).ok_or(EBADF)?;
+        let Some(entry) = stream.entries.get(stream.pos) else {
+            return Ok(std::ptr::null_mut());
+        };
+        stream.pos += 1;
+
+        let dirent = &mut *stream.dirent;
+        *dirent = unsafe { mem::zeroed() };
+        dirent.d_ino = entry.ino as _;
+        dirent.d_reclen = mem::size_of::<Dirent>() as _;
+        dirent.d_type = match entry.is_dir {
+            true => libc::DT_DIR,
+            false => libc::DT_REG,
+        };
+        let len = entry.name.len().min(dirent.d_name.len() - 1);
+        for (dst, src) in dirent.d_name.iter_mut().zip(&entry.name[..len]) {
+            *dst = *src as c_char;
+        }
+        #[cfg(target_os = "linux")]
+        {
+            dirent.d_off = stream.pos as _;
+        }
+        #[cfg(target_os = "macos")]
+        {
+            dirent.d_namlen = len as _;
+        }
+        Ok(dirent as *mut Dirent)
+    });
+    ret(result, std::ptr::null_mut())
+}
+
+// https://man7.org/linux/man-pages/man3/readdir.3.html
+fs_patch! {
+    fn readdir(dirp: *mut DIR) -> *mut Dirent
+    |real| {
+        match is_virtual_dir(dirp) {
+            true => readdir_impl(dirp),
+            false => real(),
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fs_patch! {
+    fn readdir64(dirp: *mut DIR) -> *mut Dirent
+    |real| {
+        match is_virtual_dir(dirp) {
+            true => readdir_impl(dirp),
+            false => real(),
+        }
+    }
+}
+
+// https://man7.org/linux/man-pages/man3/closedir.3.html
+fs_patch! {
+    fn closedir(dirp: *mut DIR) -> c_int
+    |real| {
+        if !is_virtual_dir(dirp) {
+            return real();
+        }
+        let result = context::with(|ctx| ctx.vfs.closedir(dirp as usize));
+        ret(result.map(|()| 0), -1)
+    }
+}
+
+// https://man7.org/linux/man-pages/man3/dirfd.3.html
+fs_patch! {
+    fn dirfd(dirp: *mut DIR) -> c_int
+    |real| {
+        if !is_virtual_dir(dirp) {
+            return real();
+        }
+        let result = context::with(|ctx| Ok(ctx.vfs.dir_stream(dirp as usize).ok_or(EBADF)?.fd));
+        ret(result, -1)
+    }
+}
+
+// Working directory
+
+/// Copy a path into `buf`, or into a buffer allocated with `malloc` if `buf` is null.
+///
+/// Fails with `error` if the path and its terminating null byte don't fit into `size` bytes. A
+/// `size` of 0 allocates as much as needed.
+fn copy_path(path: &[u8], buf: *mut c_char, size: size_t, error: c_int) -> FsResult<*mut c_char> {
+    let len = path.len() + 1;
+    if size != 0 && len > size {
+        return Err(error);
+    }
+
+    let buf = match buf.is_null() {
+        true => unsafe { libc::malloc(size.max(len)) }.cast(),
+        false => buf,
+    };
+    if buf.is_null() {
+        return Err(libc::ENOMEM);
+    }
+    unsafe {
+        std::ptr::copy_nonoverlapping(path.as_ptr().cast(), buf, path.len());
+        *buf.add(path.len()) = 0;
+    }
+    Ok(buf)
+}
+
+// https://man7.org/linux/man-pages/man3/getcwd.3.html
+fs_patch! {
+    fn getcwd(buf: *mut c_char, size: size_t) -> *mut c_char
+    |real| {
+        if !in_host() {
+            return real();
+        }
+        if !buf.is_null() && size == 0 {
+            set_errno(libc::EINVAL);
+            return std::ptr::null_mut();
+        }
+        let result = with_fs(|fs, _| fs.getcwd());
+        let result = result.and_then(|path| copy_path(&path, buf, size, libc::ERANGE));
+        ret(result, std::ptr::null_mut())
+    }
+}
+
+// https://man7.org/linux/man-pages/man2/chdir.2.html
+fs_patch! {
+    fn chdir(pathname: *const c_char) -> c_int
+    |real| {
+        let Some(base) = route(libc::AT_FDCWD) else {
+            return real();
+        };
+        let path = unsafe { path(pathname) };
+        ret(with_fs(|fs, _| fs.chdir(base, path)).map(|()| 0), -1)
+    }
+}
+
+/// Name of `realpath` in libc, which differs on Apple platforms.
+#[cfg(not(target_vendor = "apple"))]
+const REALPATH: &str = "realpath\0";
+#[cfg(target_vendor = "apple")]
+const REALPATH: &str = "realpath$DARWIN_EXTSN\0";
+
+// https://man7.org/linux/man-pages/man3/realpath.3.html
+//
+// This is not defined through `fs_patch!`, as libc exports it under a different name on Apple
+// platforms.
+#[cfg_attr(not(target_vendor = "apple"), unsafe(no_mangle))]
+#[cfg_attr(target_vendor = "apple", unsafe(export_name = "realpath$DARWIN_EXTSN"))]
+pub unsafe extern "C" fn realpath(pathname: *const c_char, resolved: *mut c_char) -> *mut c_char {
+    if !in_host() {
+        static ADDR: AtomicUsize = AtomicUsize::new(0);
+        let f: unsafe extern "C" fn(*const c_char, *mut c_char) -> *mut c_char =
+            unsafe { lookup_real(&ADDR, REALPATH) };
+        return unsafe { f(pathname, resolved) };
+    }
+
+    let path = unsafe { path(pathname) };
+    let result = match Device::from_path(path) {
+        Some(_) => Ok(path.to_vec()),
+        None => with_fs(|fs, _| fs.realpath(Base::Cwd, path)),
+    };
+    // A non-null buffer has room for `PATH_MAX` bytes.
+    let size = match resolved.is_null() {
+        true => 0,
+        false => libc::PATH_MAX as size_t,
+    };
+    let result = result.and_then(|path| copy_path(&path, resolved, size, libc::ENAMETOOLONG));
+    ret(result, std::ptr::null_mut())
+}
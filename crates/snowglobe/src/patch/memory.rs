@@ -56,11 +56,15 @@ patch! {
 patch! {
     fn realloc(p: *mut c_void, size: size_t) -> *mut c_void
     |_ctx| {
+        let new_layout = Layout::from_size_align(size, ALIGN).unwrap();
+        // Reallocating a null pointer is the same as `malloc`.
         let Some(ptr) = NonNull::new(p) else {
-            return ptr::null_mut();
+            return match ALLOCATOR.allocate(new_layout) {
+                Some(ptr) => ptr.as_ptr().cast(),
+                None => ptr::null_mut(),
+            };
         };
 
-        let new_layout = Layout::from_size_align(size, ALIGN).unwrap();
         match unsafe { ALLOCATOR.reallocate(ptr.cast(), new_layout) } {
             Some(ptr) => ptr.as_ptr().cast(),
             None => ptr::null_mut(),
@@ -1,5 +1,15 @@
+use std::io::Write;
+use std::mem;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::alloc::Stderr;
+
+mod env;
+mod fs;
 mod memory;
-mod rng;
+mod process;
+pub(crate) mod rng;
+mod subprocess;
 mod thread;
 mod time;
 
@@ -17,3 +27,37 @@ macro_rules! patch {
 }
 
 use patch;
+
+/// Look up the libc implementation of a patched function, caching it in `addr`.
+///
+/// Patches that pass calls on to libc are not defined through [`patch!`], as it borrows the context
+/// for the whole call. libc may allocate, and the patched allocator borrows the context as well.
+unsafe fn lookup_real<F: Copy>(addr: &AtomicUsize, name: &str) -> F {
+    let mut ptr = addr.load(Ordering::Relaxed);
+    if ptr == 0 {
+        ptr = unsafe { libc::dlsym(libc::RTLD_NEXT, name.as_ptr().cast()) } as usize;
+        assert!(ptr != 0, "libc function not found: {name}");
+        addr.store(ptr, Ordering::Relaxed);
+    }
+    unsafe { mem::transmute_copy(&ptr) }
+}
+
+/// Report a call that is not permitted in this simulation on stderr, with the reason why.
+///
+/// This neither allocates nor touches the context, so it works whatever state the caller is in.
+fn report_denied(func: &str, reason: &str) {
+    let _ = writeln!(
+        Stderr,
+        "`{func}` is not permitted in this simulation: {reason}"
+    );
+}
+
+/// Set the calling thread's `errno`.
+fn set_errno(value: libc::c_int) {
+    #[cfg(target_os = "linux")]
+    let errno = unsafe { libc::__errno_location() };
+    #[cfg(target_os = "macos")]
+    let errno = unsafe { libc::__error() };
+
+    unsafe { *errno = value };
+}
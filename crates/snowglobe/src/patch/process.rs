@@ -0,0 +1,198 @@
+//! Identity of the process and the system it runs on.
+//!
+//! Every simulated host is its own process, with a pid given in the order hosts were added and its
+//! turmoil host name as hostname. Users, kernel and CPU count are the same everywhere, so that
+//! nothing the program derives from them depends on the machine running the simulation.
+
+use std::ptr;
+use std::sync::atomic::AtomicUsize;
+
+use libc::{c_char, c_int, c_long, gid_t, pid_t, size_t, uid_t, utsname};
+
+use super::{lookup_real, patch, set_errno};
+#[cfg(target_os = "linux")]
+use crate::context::Context;
+
+/// Host name seen outside of hosts.
+const BUNDLE_HOSTNAME: &str = "snowglobe";
+/// Parent of every process, as if they were all started by init.
+const PARENT_PID: pid_t = 1;
+/// Added to the scheduler's ids of green threads to get their thread ids.
+#[cfg(target_os = "linux")]
+const GREEN_TID_BASE: pid_t = 1_000_000;
+/// User and group of every process.
+const UID: uid_t = 1000;
+const GID: gid_t = 1000;
+
+#[cfg(target_os = "linux")]
+const SYSNAME: &str = "Linux";
+#[cfg(target_os = "macos")]
+const SYSNAME: &str = "Darwin";
+const RELEASE: &str = "6.1.0";
+const VERSION: &str = "#1 SMP";
+
+// https://man7.org/linux/man-pages/man2/getpid.2.html
+patch! {
+    fn getpid() -> pid_t
+    |ctx| {
+        ctx.pid()
+    }
+}
+
+// https://man7.org/linux/man-pages/man2/getpid.2.html
+patch! {
+    fn getppid() -> pid_t
+    |_ctx| {
+        PARENT_PID
+    }
+}
+
+// https://man7.org/linux/man-pages/man2/gettid.2.html
+//
+// The main thread's id is the pid. Green threads get ids from the scheduler, which numbers them in
+// the order they were spawned.
+#[cfg(target_os = "linux")]
+patch! {
+    fn gettid() -> pid_t
+    |ctx| {
+        tid(ctx)
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn tid(ctx: &Context) -> pid_t {
+    match ctx.threads.current_id() {
+        0 => ctx.pid(),
+        id => GREEN_TID_BASE + id as pid_t,
+    }
+}
+
+// https://man7.org/linux/man-pages/man2/getuid.2.html
+patch! {
+    fn getuid() -> uid_t
+    |_ctx| {
+        UID
+    }
+}
+
+patch! {
+    fn geteuid() -> uid_t
+    |_ctx| {
+        UID
+    }
+}
+
+// https://man7.org/linux/man-pages/man2/getgid.2.html
+patch! {
+    fn getgid() -> gid_t
+    |_ctx| {
+        GID
+    }
+}
+
+patch! {
+    fn getegid() -> gid_t
+    |_ctx| {
+        GID
+    }
+}
+
+// https://man7.org/linux/man-pages/man2/gethostname.2.html
+patch! {
+    fn gethostname(name: *mut c_char, len: size_t) -> c_int
+    |ctx| {
+        let hostname = ctx.host.as_deref().unwrap_or(BUNDLE_HOSTNAME);
+        if hostname.len() >= len {
+            set_errno(libc::ENAMETOOLONG);
+            return -1;
+        }
+
+        unsafe { copy_str(hostname, name, len) };
+        0
+    }
+}
+
+// https://man7.org/linux/man-pages/man2/uname.2.html
+patch! {
+    fn uname(buf: *mut utsname) -> c_int
+    |ctx| {
+        let Some(buf) = (unsafe { buf.as_mut() }) else {
+            set_errno(libc::EFAULT);
+            return -1;
+        };
+        let hostname = ctx.host.as_deref().unwrap_or(BUNDLE_HOSTNAME);
+        let fields = [
+            (&mut buf.sysname, SYSNAME),
+            (&mut buf.nodename, hostname),
+            (&mut buf.release, RELEASE),
+            (&mut buf.version, VERSION),
+            (&mut buf.machine, std::env::consts::ARCH),
+        ];
+        for (field, value) in fields {
+            unsafe { copy_str(value, field.as_mut_ptr(), field.len()) };
+        }
+        #[cfg(target_os = "linux")]
+        unsafe {
+            copy_str("(none)", buf.domainname.as_mut_ptr(), buf.domainname.len())
+        };
+        0
+    }
+}
+
+/// Copy a string into a buffer of `len` bytes as a `NUL`-terminated string, truncating it if
+/// it doesn't fit.
+unsafe fn copy_str(s: &str, buf: *mut c_char, len: usize) {
+    let n = s.len().min(len - 1);
+    unsafe {
+        ptr::copy_nonoverlapping(s.as_ptr().cast(), buf, n);
+        *buf.add(n) = 0;
+    }
+}
+
+/// Get configuration information at run time.
+///
+/// The number of CPUs comes from the scene, other names are passed on to libc, see
+/// [`lookup_real`].
+///
+/// https://man7.org/linux/man-pages/man3/sysconf.3.html
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sysconf(name: c_int) -> c_long {
+    if name == libc::_SC_NPROCESSORS_ONLN || name == libc::_SC_NPROCESSORS_CONF {
+        return crate::context::with(|ctx| ctx.cpus as c_long);
+    }
+
+    static ADDR: AtomicUsize = AtomicUsize::new(0);
+    let real: unsafe extern "C" fn(c_int) -> c_long = unsafe { lookup_real(&ADDR, "sysconf\0") };
+    unsafe { real(name) }
+}
+
+// https://man7.org/linux/man-pages/man2/sched_setaffinity.2.html
+//
+// Any process may run on all CPUs of the scene. Rust's `available_parallelism` asks this before
+// `sysconf`. Only the calling thread and its process can be asked for, as other hosts are other
+// machines.
+#[cfg(target_os = "linux")]
+patch! {
+    fn sched_getaffinity(pid: pid_t, cpusetsize: size_t, mask: *mut libc::cpu_set_t) -> c_int
+    |ctx| {
+        if pid != 0 && pid != ctx.pid() && pid != tid(ctx) {
+            set_errno(libc::ESRCH);
+            return -1;
+        }
+        if mask.is_null() {
+            set_errno(libc::EFAULT);
+            return -1;
+        }
+        if cpusetsize * 8 < ctx.cpus {
+            set_errno(libc::EINVAL);
+            return -1;
+        }
+
+        let mask = unsafe { std::slice::from_raw_parts_mut(mask.cast::<u8>(), cpusetsize) };
+        mask.fill(0);
+        for cpu in 0..ctx.cpus {
+            mask[cpu / 8] |= 1 << (cpu % 8);
+        }
+        0
+    }
+}
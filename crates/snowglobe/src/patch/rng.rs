@@ -1,6 +1,8 @@
-use libc::{c_int, c_uint, c_void, size_t, ssize_t};
-use rand::Rng;
+use std::ptr;
+
+use libc::{c_double, c_int, c_long, c_uint, c_ushort, c_void, size_t, ssize_t};
 use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
 
 use super::patch;
 
@@ -41,3 +43,207 @@ patch! {
         libc::kCCSuccess
     }
 }
+
+/// State of libc's legacy random number generators.
+///
+/// The generators are seeded from the simulation seed, and can be reseeded by the program
+/// through `srand`, `srandom`, `srand48`, `seed48` and `lcong48`. Reseeding with the same seed
+/// reproduces the same sequence, but not the one glibc would produce.
+pub(crate) struct LegacyRng {
+    /// State shared by `rand` and `random`, as in glibc.
+    rand: SmallRng,
+    /// State of the `drand48` family: a 48-bit linear congruential generator.
+    x: u64,
+    a: u64,
+    c: u64,
+    /// Previous value of `x`, as returned by `seed48`.
+    old_seed: [c_ushort; 3],
+}
+
+const RAND48_MASK: u64 = (1 << 48) - 1;
+const RAND48_A: u64 = 0x5_deec_e66d;
+const RAND48_C: u64 = 0xb;
+
+impl LegacyRng {
+    pub fn new(seed: u64) -> Self {
+        let mut rng = SmallRng::seed_from_u64(seed);
+        Self {
+            rand: SmallRng::from_rng(&mut rng),
+            x: rng.random::<u64>() & RAND48_MASK,
+            a: RAND48_A,
+            c: RAND48_C,
+            old_seed: [0; 3],
+        }
+    }
+
+    /// Draw a value in `0..=RAND_MAX`.
+    fn rand(&mut self) -> c_int {
+        self.rand.random_range(0..=libc::RAND_MAX)
+    }
+
+    fn next48(&self, x: u64) -> u64 {
+        self.a.wrapping_mul(x).wrapping_add(self.c) & RAND48_MASK
+    }
+
+    /// Advance the internal state, or the external one in `xsubi` if given.
+    unsafe fn step48(&mut self, xsubi: *mut c_ushort) -> u64 {
+        if xsubi.is_null() {
+            self.x = self.next48(self.x);
+            return self.x;
+        }
+
+        let xsubi = unsafe { &mut *xsubi.cast::<[c_ushort; 3]>() };
+        let x = self.next48(from_u16s(*xsubi));
+        *xsubi = to_u16s(x);
+        x
+    }
+}
+
+fn from_u16s(v: [c_ushort; 3]) -> u64 {
+    u64::from(v[0]) | u64::from(v[1]) << 16 | u64::from(v[2]) << 32
+}
+
+fn to_u16s(x: u64) -> [c_ushort; 3] {
+    [x as c_ushort, (x >> 16) as c_ushort, (x >> 32) as c_ushort]
+}
+
+// https://man7.org/linux/man-pages/man3/rand.3.html
+patch! {
+    fn rand() -> c_int
+    |ctx| {
+        ctx.legacy_rng.rand()
+    }
+}
+
+patch! {
+    fn srand(seed: c_uint) -> ()
+    |ctx| {
+        ctx.legacy_rng.rand = SmallRng::seed_from_u64(seed.into());
+    }
+}
+
+// `rand_r` keeps its whole state in `seedp`, so it only needs to be deterministic given the seed.
+// This is the implementation suggested by POSIX.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rand_r(seedp: *mut c_uint) -> c_int {
+    let seed = unsafe { &mut *seedp };
+    *seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+    ((*seed / 65536) % 32768) as c_int
+}
+
+// https://man7.org/linux/man-pages/man3/random.3.html
+patch! {
+    fn random() -> c_long
+    |ctx| {
+        ctx.legacy_rng.rand().into()
+    }
+}
+
+patch! {
+    fn srandom(seed: c_uint) -> ()
+    |ctx| {
+        ctx.legacy_rng.rand = SmallRng::seed_from_u64(seed.into());
+    }
+}
+
+// https://man7.org/linux/man-pages/man3/drand48.3.html
+patch! {
+    fn drand48() -> c_double
+    |ctx| {
+        unsafe { ctx.legacy_rng.step48(ptr::null_mut()) as c_double / (1u64 << 48) as c_double }
+    }
+}
+
+patch! {
+    fn erand48(xsubi: *mut c_ushort) -> c_double
+    |ctx| {
+        unsafe { ctx.legacy_rng.step48(xsubi) as c_double / (1u64 << 48) as c_double }
+    }
+}
+
+patch! {
+    fn lrand48() -> c_long
+    |ctx| {
+        (unsafe { ctx.legacy_rng.step48(ptr::null_mut()) } >> 17) as c_long
+    }
+}
+
+patch! {
+    fn nrand48(xsubi: *mut c_ushort) -> c_long
+    |ctx| {
+        (unsafe { ctx.legacy_rng.step48(xsubi) } >> 17) as c_long
+    }
+}
+
+patch! {
+    fn mrand48() -> c_long
+    |ctx| {
+        (unsafe { ctx.legacy_rng.step48(ptr::null_mut()) } >> 16) as i32 as c_long
+    }
+}
+
+patch! {
+    fn jrand48(xsubi: *mut c_ushort) -> c_long
+    |ctx| {
+        (unsafe { ctx.legacy_rng.step48(xsubi) } >> 16) as i32 as c_long
+    }
+}
+
+patch! {
+    fn srand48(seedval: c_long) -> ()
+    |ctx| {
+        let rng = &mut ctx.legacy_rng;
+        rng.x = u64::from(seedval as u32) << 16 | 0x330e;
+        rng.a = RAND48_A;
+        rng.c = RAND48_C;
+    }
+}
+
+patch! {
+    fn seed48(seed16v: *mut c_ushort) -> *mut c_ushort
+    |ctx| {
+        let rng = &mut ctx.legacy_rng;
+        rng.old_seed = to_u16s(rng.x);
+        rng.x = from_u16s(unsafe { *seed16v.cast::<[c_ushort; 3]>() });
+        rng.a = RAND48_A;
+        rng.c = RAND48_C;
+        // The context is never moved or dropped, so the buffer outlives the call.
+        rng.old_seed.as_mut_ptr()
+    }
+}
+
+patch! {
+    fn lcong48(param: *mut c_ushort) -> ()
+    |ctx| {
+        let param = unsafe { &*param.cast::<[c_ushort; 7]>() };
+        let rng = &mut ctx.legacy_rng;
+        rng.x = from_u16s([param[0], param[1], param[2]]);
+        rng.a = from_u16s([param[3], param[4], param[5]]);
+        rng.c = param[6].into();
+    }
+}
+
+// https://man7.org/linux/man-pages/man3/arc4random.3.html
+patch! {
+    fn arc4random() -> u32
+    |ctx| {
+        ctx.rng.random()
+    }
+}
+
+patch! {
+    fn arc4random_buf(buf: *mut c_void, nbytes: size_t) -> ()
+    |ctx| {
+        unsafe { fill_raw(&mut ctx.rng, buf.cast(), nbytes) };
+    }
+}
+
+patch! {
+    fn arc4random_uniform(upper_bound: u32) -> u32
+    |ctx| {
+        match upper_bound {
+            0 | 1 => 0,
+            _ => ctx.rng.random_range(0..upper_bound),
+        }
+    }
+}
@@ -0,0 +1,148 @@
+//! Calls that start subprocesses.
+//!
+//! A subprocess runs outside of the simulation, with the real clock, filesystem, network and
+//! heap, so all of these calls fail with `EPERM`, and are reported with [`report_denied`].
+
+use libc::{FILE, c_char, c_int, pid_t, posix_spawn_file_actions_t, posix_spawnattr_t};
+
+use super::{report_denied, set_errno};
+
+/// Report a blocked call.
+fn report(func: &str) {
+    report_denied(func, "subprocesses run outside of it");
+}
+
+/// Report a blocked call and set `errno`.
+fn deny(func: &str) {
+    report(func);
+    set_errno(libc::EPERM);
+}
+
+// https://man7.org/linux/man-pages/man2/fork.2.html
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn fork() -> pid_t {
+    deny("fork");
+    -1
+}
+
+// https://man7.org/linux/man-pages/man2/vfork.2.html
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn vfork() -> pid_t {
+    deny("vfork");
+    -1
+}
+
+// https://man7.org/linux/man-pages/man2/execve.2.html
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn execve(
+    _path: *const c_char,
+    _argv: *const *const c_char,
+    _envp: *const *const c_char,
+) -> c_int {
+    deny("execve");
+    -1
+}
+
+// https://man7.org/linux/man-pages/man3/fexecve.3.html
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn fexecve(
+    _fd: c_int,
+    _argv: *const *const c_char,
+    _envp: *const *const c_char,
+) -> c_int {
+    deny("fexecve");
+    -1
+}
+
+// https://man7.org/linux/man-pages/man3/exec.3.html
+//
+// libc implements these on top of `execve` without going through the patched symbol.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn execv(_path: *const c_char, _argv: *const *const c_char) -> c_int {
+    deny("execv");
+    -1
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn execvp(_file: *const c_char, _argv: *const *const c_char) -> c_int {
+    deny("execvp");
+    -1
+}
+
+#[cfg(target_os = "linux")]
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn execvpe(
+    _file: *const c_char,
+    _argv: *const *const c_char,
+    _envp: *const *const c_char,
+) -> c_int {
+    deny("execvpe");
+    -1
+}
+
+// The `execl` family is variadic. None of the arguments are read, so they are left out.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn execl(_path: *const c_char) -> c_int {
+    deny("execl");
+    -1
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn execlp(_file: *const c_char) -> c_int {
+    deny("execlp");
+    -1
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn execle(_path: *const c_char) -> c_int {
+    deny("execle");
+    -1
+}
+
+// https://man7.org/linux/man-pages/man3/system.3.html
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn system(command: *const c_char) -> c_int {
+    // A null command asks whether a shell is available.
+    if command.is_null() {
+        return 0;
+    }
+
+    deny("system");
+    -1
+}
+
+// https://man7.org/linux/man-pages/man3/popen.3.html
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn popen(_command: *const c_char, _mode: *const c_char) -> *mut FILE {
+    deny("popen");
+    std::ptr::null_mut()
+}
+
+// https://man7.org/linux/man-pages/man3/posix_spawn.3.html
+//
+// Errors are returned rather than stored in `errno`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn posix_spawn(
+    _pid: *mut pid_t,
+    _path: *const c_char,
+    _file_actions: *const posix_spawn_file_actions_t,
+    _attrp: *const posix_spawnattr_t,
+    _argv: *const *mut c_char,
+    _envp: *const *mut c_char,
+) -> c_int {
+    report("posix_spawn");
+    libc::EPERM
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn posix_spawnp(
+    _pid: *mut pid_t,
+    _file: *const c_char,
+    _file_actions: *const posix_spawn_file_actions_t,
+    _attrp: *const posix_spawnattr_t,
+    _argv: *const *mut c_char,
+    _envp: *const *mut c_char,
+) -> c_int {
+    report("posix_spawnp");
+    libc::EPERM
+}
@@ -1,24 +1,174 @@
-use libc::{c_int, c_void, pid_t, pthread_attr_t, pthread_t};
+//! Threads of the simulated program.
+//!
+//! Spawning threads fails unless the scene runs them as green threads, see [`crate::green`].
+//! Green threads are scheduled through the calls they block in, so these patches pass calls on
+//! to libc when no green thread was spawned, see [`lookup_real`].
 
-use super::patch;
+use std::sync::atomic::AtomicUsize;
 
-// https://man7.org/linux/man-pages/man2/getpid.2.html
-patch! {
-    fn getpid() -> pid_t
-    |_ctx| {
-        12345
-    }
+use libc::{c_int, c_void, pthread_attr_t, pthread_t};
+
+use super::{lookup_real, report_denied};
+use crate::context;
+use crate::green::{self, ThreadMode};
+
+/// Look up the libc implementation of a patched function.
+macro_rules! real {
+    ($name:ident: $ty:ty) => {{
+        static ADDR: AtomicUsize = AtomicUsize::new(0);
+        let f: $ty = unsafe { lookup_real(&ADDR, concat!(stringify!($name), "\0")) };
+        f
+    }};
+}
+
+/// Whether the calling thread is scheduled by [`green`].
+fn scheduled() -> bool {
+    context::with(|ctx| ctx.threads.is_active())
 }
 
 // https://man7.org/linux/man-pages/man3/pthread_create.3.html
-patch! {
-    fn pthread_create(
-        _native: *mut pthread_t,
-        _attr: *const pthread_attr_t,
-        _f: extern "C" fn(*mut c_void) -> *mut c_void,
-        _value: *mut c_void,
-    ) -> c_int
-    |_ctx| {
-        libc::EPERM
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pthread_create(
+    native: *mut pthread_t,
+    attr: *const pthread_attr_t,
+    f: extern "C" fn(*mut c_void) -> *mut c_void,
+    value: *mut c_void,
+) -> c_int {
+    // Green threads rely on std's synchronization being built on futexes.
+    let mode = context::with(|ctx| ctx.threads.mode);
+    if !cfg!(target_os = "linux") || mode != ThreadMode::Green {
+        report_denied(
+            "pthread_create",
+            "threads would run outside of it \
+             (use `#[scene(threads = \"green\")]` to run them as green threads)",
+        );
+        return libc::EPERM;
+    }
+
+    let real = real!(pthread_create: unsafe extern "C" fn(
+        *mut pthread_t,
+        *const pthread_attr_t,
+        extern "C" fn(*mut c_void) -> *mut c_void,
+        *mut c_void,
+    ) -> c_int);
+    green::spawn(native, attr, f, value, real)
+}
+
+// https://man7.org/linux/man-pages/man3/pthread_join.3.html
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pthread_join(thread: pthread_t, retval: *mut *mut c_void) -> c_int {
+    // Green threads never exit, see `green::start_green`.
+    if scheduled()
+        && let Some(ret) = green::join(thread)
+    {
+        if !retval.is_null() {
+            unsafe { *retval = ret };
+        }
+        return 0;
+    }
+
+    let real = real!(pthread_join: unsafe extern "C" fn(pthread_t, *mut *mut c_void) -> c_int);
+    unsafe { real(thread, retval) }
+}
+
+// https://man7.org/linux/man-pages/man2/sched_yield.2.html
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sched_yield() -> c_int {
+    if scheduled() {
+        green::yield_now();
+        return 0;
+    }
+
+    let real = real!(sched_yield: unsafe extern "C" fn() -> c_int);
+    unsafe { real() }
+}
+
+/// Make a system call, scheduling green threads on futex operations.
+///
+/// `syscall` is variadic, but on the supported platforms integer arguments are passed the same
+/// way to variadic and regular functions, so it is defined with the maximum number of arguments.
+///
+/// https://man7.org/linux/man-pages/man2/syscall.2.html
+#[cfg(target_os = "linux")]
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn syscall(
+    num: libc::c_long,
+    a1: libc::c_long,
+    a2: libc::c_long,
+    a3: libc::c_long,
+    a4: libc::c_long,
+    a5: libc::c_long,
+    a6: libc::c_long,
+) -> libc::c_long {
+    if num == libc::SYS_futex && scheduled() {
+        return unsafe {
+            futex(
+                a1 as usize,
+                a2 as c_int,
+                a3 as u32,
+                a4 as *const libc::timespec,
+            )
+        };
+    }
+
+    type Syscall = unsafe extern "C" fn(
+        libc::c_long,
+        libc::c_long,
+        libc::c_long,
+        libc::c_long,
+        libc::c_long,
+        libc::c_long,
+        libc::c_long,
+    ) -> libc::c_long;
+    let real = real!(syscall: Syscall);
+    unsafe { real(num, a1, a2, a3, a4, a5, a6) }
+}
+
+/// Wait on or wake a futex word as a green thread.
+///
+/// Only the operations std uses are supported.
+///
+/// https://man7.org/linux/man-pages/man2/futex.2.html
+#[cfg(target_os = "linux")]
+unsafe fn futex(addr: usize, op: c_int, val: u32, timeout: *const libc::timespec) -> libc::c_long {
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::time::Duration;
+
+    use super::set_errno;
+
+    match op & libc::FUTEX_CMD_MASK {
+        cmd @ (libc::FUTEX_WAIT | libc::FUTEX_WAIT_BITSET) => {
+            let word = unsafe { &*(addr as *const AtomicU32) };
+            if word.load(Ordering::SeqCst) != val {
+                set_errno(libc::EAGAIN);
+                return -1;
+            }
+
+            // `FUTEX_WAIT` takes a relative timeout, `FUTEX_WAIT_BITSET` an absolute one.
+            let timeout = unsafe { timeout.as_ref() }
+                .map(|ts| Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32));
+            let deadline = timeout.map(|timeout| {
+                context::with(|ctx| match cmd {
+                    libc::FUTEX_WAIT => ctx.monotonic() + timeout,
+                    _ if op & libc::FUTEX_CLOCK_REALTIME != 0 => {
+                        ctx.monotonic() + timeout.saturating_sub(ctx.realtime())
+                    }
+                    _ => timeout,
+                })
+            });
+            if green::futex_wait(addr, deadline) {
+                set_errno(libc::ETIMEDOUT);
+                return -1;
+            }
+            0
+        }
+        libc::FUTEX_WAKE | libc::FUTEX_WAKE_BITSET => {
+            let count = usize::try_from(val).unwrap_or(usize::MAX);
+            green::futex_wake(addr, count) as libc::c_long
+        }
+        _ => {
+            set_errno(libc::ENOSYS);
+            -1
+        }
     }
 }
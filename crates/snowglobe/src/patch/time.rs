@@ -1,15 +1,301 @@
-use libc::{c_int, clockid_t, timespec};
+use std::io::Write;
+use std::time::Duration;
 
-use super::patch;
+use libc::{
+    c_int, c_short, c_uint, c_ushort, c_void, clock_t, clockid_t, time_t, timespec, timeval,
+    useconds_t,
+};
+
+use super::{patch, set_errno};
+use crate::alloc::Stderr;
+use crate::clock::SleepMode;
+use crate::context::{self, Context};
+use crate::green;
+
+// Not exposed by the `libc` crate, but identical on all supported platforms.
+const CLOCKS_PER_SEC: clock_t = 1_000_000;
+const TIME_UTC: c_int = 1;
+
+/// `struct timeb` from `<sys/timeb.h>`.
+#[repr(C)]
+pub struct Timeb {
+    time: time_t,
+    millitm: c_ushort,
+    timezone: c_short,
+    dstflag: c_short,
+}
+
+/// `struct timezone` from `<sys/time.h>`.
+#[repr(C)]
+struct Timezone {
+    tz_minuteswest: c_int,
+    tz_dsttime: c_int,
+}
+
+/// Read the simulated value of the given clock.
+///
+/// Realtime clocks start at the configured epoch, monotonic clocks start at zero. The process is
+/// considered busy for the whole simulation, so CPU-time clocks advance with simulated time.
+/// Realtime and monotonic clocks include the skew of the host that is currently running.
+fn clock_value(ctx: &Context, clk_id: clockid_t) -> Option<Duration> {
+    let realtime = ctx.realtime();
+    let monotonic = ctx.monotonic();
+    match clk_id {
+        libc::CLOCK_REALTIME => Some(realtime),
+        libc::CLOCK_MONOTONIC | libc::CLOCK_MONOTONIC_RAW => Some(monotonic),
+        libc::CLOCK_PROCESS_CPUTIME_ID | libc::CLOCK_THREAD_CPUTIME_ID => Some(monotonic),
+        #[cfg(target_os = "linux")]
+        libc::CLOCK_REALTIME_COARSE | libc::CLOCK_REALTIME_ALARM => Some(realtime),
+        #[cfg(target_os = "linux")]
+        libc::CLOCK_MONOTONIC_COARSE | libc::CLOCK_BOOTTIME | libc::CLOCK_BOOTTIME_ALARM => {
+            Some(monotonic)
+        }
+        // TAI is ahead of UTC by the accumulated leap seconds, 37 since 2017.
+        #[cfg(target_os = "linux")]
+        libc::CLOCK_TAI => Some(realtime + Duration::from_secs(37)),
+        #[cfg(target_os = "macos")]
+        libc::CLOCK_MONOTONIC_RAW_APPROX
+        | libc::CLOCK_UPTIME_RAW
+        | libc::CLOCK_UPTIME_RAW_APPROX => Some(monotonic),
+        _ => None,
+    }
+}
 
 // https://man7.org/linux/man-pages/man3/clock_gettime.3.html
 patch! {
-    fn clock_gettime(_clk_id: clockid_t, tp: *mut timespec) -> c_int
+    fn clock_gettime(clk_id: clockid_t, tp: *mut timespec) -> c_int
     |ctx| {
+        let Some(value) = clock_value(ctx, clk_id) else {
+            set_errno(libc::EINVAL);
+            return -1;
+        };
+
         unsafe {
-            (*tp).tv_sec = ctx.time.as_secs() as i64;
-            (*tp).tv_nsec = ctx.time.subsec_nanos() as i64;
+            (*tp).tv_sec = value.as_secs() as _;
+            (*tp).tv_nsec = value.subsec_nanos() as _;
         }
         0
     }
 }
+
+// https://man7.org/linux/man-pages/man3/clock_getres.3.html
+patch! {
+    fn clock_getres(clk_id: clockid_t, res: *mut timespec) -> c_int
+    |ctx| {
+        if clock_value(ctx, clk_id).is_none() {
+            set_errno(libc::EINVAL);
+            return -1;
+        }
+
+        // All simulated clocks are reported with nanosecond precision.
+        if !res.is_null() {
+            unsafe {
+                (*res).tv_sec = 0;
+                (*res).tv_nsec = 1;
+            }
+        }
+        0
+    }
+}
+
+// https://man7.org/linux/man-pages/man2/gettimeofday.2.html
+patch! {
+    fn gettimeofday(tv: *mut timeval, tz: *mut c_void) -> c_int
+    |ctx| {
+        let now = ctx.realtime();
+        if !tv.is_null() {
+            unsafe {
+                (*tv).tv_sec = now.as_secs() as _;
+                (*tv).tv_usec = now.subsec_micros() as _;
+            }
+        }
+        // Simulated hosts run in UTC.
+        if !tz.is_null() {
+            let tz = tz.cast::<Timezone>();
+            unsafe {
+                (*tz).tz_minuteswest = 0;
+                (*tz).tz_dsttime = 0;
+            }
+        }
+        0
+    }
+}
+
+// https://man7.org/linux/man-pages/man2/time.2.html
+patch! {
+    fn time(tloc: *mut time_t) -> time_t
+    |ctx| {
+        let secs = ctx.realtime().as_secs() as time_t;
+        if !tloc.is_null() {
+            unsafe { *tloc = secs };
+        }
+        secs
+    }
+}
+
+// https://man7.org/linux/man-pages/man3/clock.3.html
+patch! {
+    fn clock() -> clock_t
+    |ctx| {
+        let cpu_time = clock_value(ctx, libc::CLOCK_PROCESS_CPUTIME_ID).unwrap();
+        (cpu_time.as_micros() * CLOCKS_PER_SEC as u128 / 1_000_000) as clock_t
+    }
+}
+
+// https://en.cppreference.com/w/c/chrono/timespec_get
+patch! {
+    fn timespec_get(ts: *mut timespec, base: c_int) -> c_int
+    |ctx| {
+        if base != TIME_UTC {
+            return 0;
+        }
+
+        let now = ctx.realtime();
+        unsafe {
+            (*ts).tv_sec = now.as_secs() as _;
+            (*ts).tv_nsec = now.subsec_nanos() as _;
+        }
+        base
+    }
+}
+
+// https://man7.org/linux/man-pages/man3/ftime.3.html
+patch! {
+    fn ftime(tp: *mut Timeb) -> c_int
+    |ctx| {
+        let now = ctx.realtime();
+        unsafe {
+            (*tp).time = now.as_secs() as _;
+            (*tp).millitm = now.subsec_millis() as _;
+            (*tp).timezone = 0;
+            (*tp).dstflag = 0;
+        }
+        0
+    }
+}
+
+/// Convert a `timespec` to a duration, returning `None` if it is invalid.
+fn timespec_to_duration(ts: &timespec) -> Option<Duration> {
+    let secs = u64::try_from(ts.tv_sec).ok()?;
+    let nanos = u32::try_from(ts.tv_nsec)
+        .ok()
+        .filter(|n| *n < 1_000_000_000)?;
+    Some(Duration::new(secs, nanos))
+}
+
+/// Simulate a blocking sleep of the current host, according to the scene's [`SleepMode`].
+///
+/// Green threads block until the sleep is over, letting other threads run.
+///
+/// Returns `false` if sleeping is not permitted, after reporting it and failing the run. The
+/// sleep patches are called from C, so they can't panic: unwinding out of them would abort the
+/// process before the run is reported.
+fn simulate_sleep(func: &str, duration: Duration) -> bool {
+    if context::is_green() {
+        green::sleep(duration);
+        return true;
+    }
+
+    let (mode, advanced) = context::with(|ctx| match ctx.sleep {
+        SleepMode::Deny => (SleepMode::Deny, false),
+        SleepMode::Advance => (SleepMode::Advance, ctx.advance_host_clock(duration)),
+    });
+
+    let _ = match (mode, advanced) {
+        (SleepMode::Deny, _) => writeln!(
+            Stderr,
+            "`{func}` is not permitted in this simulation: blocking sleeps stall all hosts \
+             (use `#[scene(sleep = \"advance\")]` to advance the sleeping host's clock instead)"
+        ),
+        (SleepMode::Advance, false) => writeln!(
+            Stderr,
+            "`{func}` is not permitted outside of a simulated host: there is no host clock to \
+             advance"
+        ),
+        (SleepMode::Advance, true) => return true,
+    };
+    context::fail("a blocking sleep is not permitted in this simulation");
+    false
+}
+
+// https://man7.org/linux/man-pages/man2/nanosleep.2.html
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn nanosleep(req: *const timespec, rem: *mut timespec) -> c_int {
+    let Some(req) = (unsafe { req.as_ref() }) else {
+        set_errno(libc::EFAULT);
+        return -1;
+    };
+    let Some(duration) = timespec_to_duration(req) else {
+        set_errno(libc::EINVAL);
+        return -1;
+    };
+
+    if !simulate_sleep("nanosleep", duration) {
+        set_errno(libc::EPERM);
+        return -1;
+    }
+
+    if !rem.is_null() {
+        unsafe {
+            (*rem).tv_sec = 0;
+            (*rem).tv_nsec = 0;
+        }
+    }
+    0
+}
+
+// https://man7.org/linux/man-pages/man2/clock_nanosleep.2.html
+#[cfg(target_os = "linux")]
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn clock_nanosleep(
+    clk_id: clockid_t,
+    flags: c_int,
+    req: *const timespec,
+    rem: *mut timespec,
+) -> c_int {
+    let Some(req) = (unsafe { req.as_ref() }) else {
+        return libc::EFAULT;
+    };
+    let Some(request) = timespec_to_duration(req) else {
+        return libc::EINVAL;
+    };
+    let Some(now) = context::with(|ctx| clock_value(ctx, clk_id)) else {
+        return libc::EINVAL;
+    };
+
+    let duration = if flags & libc::TIMER_ABSTIME != 0 {
+        request.saturating_sub(now)
+    } else {
+        request
+    };
+    if !simulate_sleep("clock_nanosleep", duration) {
+        return libc::EPERM;
+    }
+
+    if !rem.is_null() && flags & libc::TIMER_ABSTIME == 0 {
+        unsafe {
+            (*rem).tv_sec = 0;
+            (*rem).tv_nsec = 0;
+        }
+    }
+    0
+}
+
+// https://man7.org/linux/man-pages/man3/sleep.3.html
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sleep(seconds: c_uint) -> c_uint {
+    if !simulate_sleep("sleep", Duration::from_secs(seconds.into())) {
+        return seconds;
+    }
+    0
+}
+
+// https://man7.org/linux/man-pages/man3/usleep.3.html
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn usleep(usec: useconds_t) -> c_int {
+    if !simulate_sleep("usleep", Duration::from_micros(usec.into())) {
+        set_errno(libc::EPERM);
+        return -1;
+    }
+    0
+}
@@ -0,0 +1,102 @@
+//! Property assertions.
+//!
+//! Each assertion site registers itself in [`PROPERTIES`] at link time, so a run can report on
+//! all sites, including the ones it never reached.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use snowglobe_proto as proto;
+use snowglobe_proto::PropertyKind;
+
+use crate::cli::__private::PROPERTIES;
+
+/// Assert that a condition holds every time it is evaluated.
+///
+/// A violation fails the run.
+#[macro_export]
+macro_rules! always {
+    ($cond:expr, $message:literal $(,)?) => {
+        $crate::__property!(Always, $cond, $message)
+    };
+}
+
+/// Assert that a condition holds at least once across a fuzzing campaign.
+#[macro_export]
+macro_rules! sometimes {
+    ($cond:expr, $message:literal $(,)?) => {
+        $crate::__property!(Sometimes, $cond, $message)
+    };
+}
+
+/// Assert that a code path is reached at least once across a fuzzing campaign.
+#[macro_export]
+macro_rules! reachable {
+    ($message:literal $(,)?) => {
+        $crate::__property!(Reachable, true, $message)
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __property {
+    ($kind:ident, $cond:expr, $message:literal) => {{
+        use $crate::__private::*;
+
+        #[linkme::distributed_slice(PROPERTIES)]
+        #[linkme(crate = linkme)]
+        static PROPERTY: Property = Property::new(
+            PropertyKind::$kind,
+            $message,
+            concat!(file!(), ":", line!(), ":", column!()),
+        );
+
+        PROPERTY.record($cond);
+    }};
+}
+
+pub struct Property {
+    kind: PropertyKind,
+    message: &'static str,
+    location: &'static str,
+    hits: AtomicU64,
+    passes: AtomicU64,
+}
+
+impl Property {
+    pub const fn new(kind: PropertyKind, message: &'static str, location: &'static str) -> Self {
+        Self {
+            kind,
+            message,
+            location,
+            hits: AtomicU64::new(0),
+            passes: AtomicU64::new(0),
+        }
+    }
+
+    pub fn record(&self, cond: bool) {
+        self.hits.fetch_add(1, Ordering::Relaxed);
+        if cond {
+            self.passes.fetch_add(1, Ordering::Relaxed);
+        } else if self.kind == PropertyKind::Always {
+            panic!("property violated: {} ({})", self.message, self.location);
+        }
+    }
+
+    fn to_proto(&self) -> proto::Property {
+        proto::Property {
+            kind: self.kind,
+            message: self.message.into(),
+            location: self.location.into(),
+            hits: self.hits.load(Ordering::Relaxed),
+            passes: self.passes.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Collect the current status of all property assertion sites.
+pub(crate) fn report() -> proto::Report {
+    let mut properties: Vec<_> = PROPERTIES.iter().map(Property::to_proto).collect();
+    properties.sort_by(|a, b| a.location.cmp(&b.location));
+
+    proto::Report { properties }
+}
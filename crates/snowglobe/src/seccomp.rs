@@ -0,0 +1,198 @@
+//! Strict mode: catch system calls that bypass the patches.
+//!
+//! Patches replace libc functions, so raw `syscall`s, inline assembly and statically linked code
+//! reach the kernel unnoticed. In strict mode, a seccomp filter traps the system calls whose
+//! results the simulation controls, and the run fails with the call and a backtrace, whose
+//! addresses are not resolved to symbols. Calls made through the vDSO, like most
+//! `clock_gettime`s, never enter the kernel and can't be caught.
+//!
+//! `getpid` and friends are not trapped, as libc uses them internally to abort, nor is
+//! `sched_getaffinity`, which `pthread_getattr_np` uses when std starts a thread. New threads are
+//! created with `clone`, so only `clone`s that don't create a thread are trapped, and `clone3`,
+//! whose flags the filter can't read, fails with `ENOSYS` to make libc fall back to `clone`.
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn install() -> crate::Result {
+    Err("strict mode requires Linux".into())
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) use linux::install;
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::fmt::{self, Write};
+    use std::io;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::{mem, ptr};
+
+    use libc::{c_int, c_long, c_uint, c_void, sock_filter, sock_fprog};
+
+    use crate::Result;
+
+    /// System calls trapped in strict mode.
+    const TRAPPED: &[(c_long, &str)] = &[
+        (libc::SYS_getrandom, "getrandom"),
+        (libc::SYS_clock_gettime, "clock_gettime"),
+        (libc::SYS_gettimeofday, "gettimeofday"),
+        #[cfg(target_arch = "x86_64")]
+        (libc::SYS_time, "time"),
+        (libc::SYS_nanosleep, "nanosleep"),
+        (libc::SYS_clock_nanosleep, "clock_nanosleep"),
+        (libc::SYS_uname, "uname"),
+        (libc::SYS_socket, "socket"),
+        #[cfg(target_arch = "x86_64")]
+        (libc::SYS_fork, "fork"),
+        #[cfg(target_arch = "x86_64")]
+        (libc::SYS_vfork, "vfork"),
+        (libc::SYS_clone, "clone"),
+        (libc::SYS_execve, "execve"),
+        (libc::SYS_execveat, "execveat"),
+    ];
+
+    #[cfg(target_arch = "x86_64")]
+    const AUDIT_ARCH: u32 = 0xc000_003e;
+    #[cfg(target_arch = "aarch64")]
+    const AUDIT_ARCH: u32 = 0xc000_00b7;
+
+    /// Offsets into `struct seccomp_data`.
+    const NR: u32 = 0;
+    const ARCH: u32 = 4;
+    /// Lower half of the first argument, `clone`'s flags.
+    const ARG0: u32 = 16;
+
+    /// The `_sigsys` part of `siginfo_t`.
+    #[repr(C)]
+    struct SigsysInfo {
+        signo: c_int,
+        errno: c_int,
+        code: c_int,
+        call_addr: *mut c_void,
+        syscall: c_int,
+        arch: c_uint,
+    }
+
+    /// Install the filter for the calling thread and the threads it spawns.
+    pub(crate) fn install() -> Result {
+        // Looking up `write`, and loading the unwinder on `backtrace`'s first call, are not
+        // async-signal-safe, so both are done before the handler may run.
+        let write = unsafe { libc::dlsym(libc::RTLD_NEXT, c"write".as_ptr()) };
+        WRITE.store(write as usize, Ordering::Relaxed);
+        #[cfg(target_env = "gnu")]
+        unsafe {
+            libc::backtrace([ptr::null_mut(); 1].as_mut_ptr(), 1);
+        }
+
+        let mut action: libc::sigaction = unsafe { mem::zeroed() };
+        action.sa_sigaction = report as *const () as usize;
+        action.sa_flags = libc::SA_SIGINFO;
+        if unsafe { libc::sigaction(libc::SIGSYS, &action, ptr::null_mut()) } != 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+
+        let mut filter = filter();
+        let program = sock_fprog {
+            len: filter.len() as u16,
+            filter: filter.as_mut_ptr(),
+        };
+        unsafe {
+            if libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0
+                || libc::prctl(libc::PR_SET_SECCOMP, libc::SECCOMP_MODE_FILTER, &program) != 0
+            {
+                return Err(io::Error::last_os_error().into());
+            }
+        }
+        Ok(())
+    }
+
+    fn filter() -> Vec<sock_filter> {
+        use libc::{BPF_ABS, BPF_JEQ, BPF_JMP, BPF_JSET, BPF_K, BPF_LD, BPF_RET, BPF_W};
+
+        let load = |offset| unsafe { libc::BPF_STMT((BPF_LD | BPF_W | BPF_ABS) as u16, offset) };
+        let ret = |action| unsafe { libc::BPF_STMT((BPF_RET | BPF_K) as u16, action) };
+        // Skip `n` instructions unless the accumulator equals `k`.
+        let skip_ne = |k, n| unsafe { libc::BPF_JUMP((BPF_JMP | BPF_JEQ | BPF_K) as u16, k, 0, n) };
+        let trap = ret(libc::SECCOMP_RET_TRAP);
+
+        let mut filter = vec![
+            // Calls through another ABI have different numbers.
+            load(ARCH),
+            unsafe { libc::BPF_JUMP((BPF_JMP | BPF_JEQ | BPF_K) as u16, AUDIT_ARCH, 1, 0) },
+            trap,
+            load(NR),
+            skip_ne(libc::SYS_clone3 as u32, 1),
+            ret(libc::SECCOMP_RET_ERRNO | libc::ENOSYS as u32),
+        ];
+        for (nr, _) in TRAPPED {
+            if *nr == libc::SYS_clone {
+                continue;
+            }
+            filter.extend([skip_ne(*nr as u32, 1), trap]);
+        }
+        // Threads are allowed, see `green`.
+        let is_thread = unsafe {
+            libc::BPF_JUMP(
+                (BPF_JMP | BPF_JSET | BPF_K) as u16,
+                libc::CLONE_THREAD as u32,
+                1,
+                0,
+            )
+        };
+        filter.extend([
+            skip_ne(libc::SYS_clone as u32, 3),
+            load(ARG0),
+            is_thread,
+            trap,
+            ret(libc::SECCOMP_RET_ALLOW),
+        ]);
+        filter
+    }
+
+    /// Report a trapped system call, and fail the run.
+    ///
+    /// This runs in a signal handler, interrupting whatever the thread was doing, so it only
+    /// makes async-signal-safe calls: it writes with libc's `write`, bypassing the patches, and
+    /// leaves symbolizing the backtrace's addresses to the reader.
+    extern "C" fn report(_signal: c_int, info: *mut libc::siginfo_t, _context: *mut c_void) {
+        let nr = unsafe { (*info.cast::<SigsysInfo>()).syscall };
+        let name = TRAPPED
+            .iter()
+            .find(|(n, _)| *n == c_long::from(nr))
+            .map_or("unknown", |(_, name)| name);
+        let _ = writeln!(
+            RawStderr,
+            "`{name}` (syscall {nr}) bypassed snowglobe's patches, its result would escape the \
+             simulation\nbacktrace:"
+        );
+
+        #[cfg(target_env = "gnu")]
+        unsafe {
+            let mut frames = [ptr::null_mut(); 64];
+            let n = libc::backtrace(frames.as_mut_ptr(), frames.len() as c_int);
+            libc::backtrace_symbols_fd(frames.as_ptr(), n, libc::STDERR_FILENO);
+        }
+        unsafe { libc::_exit(1) };
+    }
+
+    /// libc's `write`, looked up when strict mode is installed.
+    static WRITE: AtomicUsize = AtomicUsize::new(0);
+
+    /// Standard error, written to without going through the patched `write`.
+    struct RawStderr;
+
+    impl fmt::Write for RawStderr {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            let write: unsafe extern "C" fn(c_int, *const c_void, usize) -> isize =
+                unsafe { mem::transmute(WRITE.load(Ordering::Relaxed)) };
+            let mut buf = s.as_bytes();
+            while !buf.is_empty() {
+                let n = unsafe { write(libc::STDERR_FILENO, buf.as_ptr().cast(), buf.len()) };
+                if n <= 0 {
+                    return Err(fmt::Error);
+                }
+                buf = &buf[n as usize..];
+            }
+            Ok(())
+        }
+    }
+}
@@ -1,20 +1,48 @@
+use std::collections::BTreeSet;
+use std::net::IpAddr;
 use std::time::Duration;
+use std::{mem, thread};
 
+use snowglobe_proto::{EventKind, MessageEvent};
+use tracing::{error, info};
 use turmoil::ToIpAddr;
 
-use crate::{Result, context};
+use crate::clock::{Clock, HostClock};
+use crate::nemesis::Nemesis;
+use crate::vfs::DiskFaults;
+use crate::{Result, context, event, green, host, tap};
 
-pub struct Sim(turmoil::Sim<'static>);
+type MessageHandler = Box<dyn FnMut(&MessageEvent)>;
+
+pub struct Sim {
+    inner: turmoil::Sim<'static>,
+    network: NetworkState,
+    /// Crashed hosts waiting to be restarted, with their restart times.
+    restarts: Vec<(Duration, String)>,
+    nemesis: Option<Nemesis>,
+    invariants: Vec<Invariant>,
+    message_handlers: Vec<MessageHandler>,
+    /// Whether a client was added, without which the simulation never finishes on its own.
+    has_clients: bool,
+}
 
 impl From<turmoil::Sim<'static>> for Sim {
     fn from(sim: turmoil::Sim<'static>) -> Self {
-        Self(sim)
+        Self {
+            inner: sim,
+            network: NetworkState::default(),
+            restarts: Vec::new(),
+            nemesis: None,
+            invariants: Vec::new(),
+            message_handlers: Vec::new(),
+            has_clients: false,
+        }
     }
 }
 
 impl Sim {
     pub fn elapsed(&self) -> Duration {
-        self.0.elapsed()
+        self.inner.elapsed()
     }
 
     pub fn host<F, Fut>(&mut self, addr: impl ToIpAddr, host: F)
@@ -22,23 +50,233 @@ impl Sim {
         F: Fn() -> Fut + 'static,
         Fut: Future<Output = Result> + 'static,
     {
-        self.0.host(addr, host)
+        let ip = self.inner.lookup(addr);
+        self.inner.host(ip, host);
+        context::register_host(&self.host_name(ip));
     }
 
     pub fn client<Fut>(&mut self, addr: impl ToIpAddr, client: Fut)
     where
         Fut: Future<Output = Result> + 'static,
     {
-        self.0.client(addr, client)
+        let ip = self.inner.lookup(addr);
+        self.inner.client(ip, client);
+        context::register_host(&self.host_name(ip));
+        self.has_clients = true;
+    }
+
+    /// Crash a host, dropping all its in-memory state.
+    ///
+    /// Data the host did not sync to disk is lost or torn. The host stays down until it is
+    /// restarted with [`Sim::bounce`].
+    pub fn crash(&mut self, addr: impl ToIpAddr) {
+        let host = self.host_name(addr);
+        self.restarts.retain(|(_, h)| *h != host);
+        self.inner.crash(&*host);
+        self.crash_process(&host);
+        event::emit(EventKind::Crash { host });
+    }
+
+    /// Restart a host, dropping all its in-memory state.
+    ///
+    /// The host's software is started again from the factory passed to [`Sim::host`]. If the
+    /// host is currently crashed, it is brought back up. Only the host's process restarts, so
+    /// data it did not sync to disk is kept.
+    pub fn bounce(&mut self, addr: impl ToIpAddr) {
+        let host = self.host_name(addr);
+        self.restarts.retain(|(_, h)| *h != host);
+        self.inner.bounce(&*host);
+        context::with(|ctx| {
+            ctx.vfs.close_all(&host);
+            ctx.env.reset(&host);
+        });
+        green::kill(&host);
+        event::emit(EventKind::Bounce { host });
+    }
+
+    /// Crash a host and restart it after the given duration of simulated time.
+    ///
+    /// As with [`Sim::crash`], data the host did not sync to disk is lost or torn. A restart
+    /// already pending for the host is replaced.
+    pub fn restart_after(&mut self, addr: impl ToIpAddr, duration: Duration) {
+        let host = self.host_name(addr);
+        self.restarts.retain(|(_, h)| *h != host);
+        self.inner.crash(&*host);
+        self.crash_process(&host);
+        event::emit(EventKind::Crash { host: host.clone() });
+
+        let restart_at = self.elapsed() + duration;
+        self.restarts.push((restart_at, host));
+    }
+
+    /// Check whether a host has software running.
+    pub fn is_host_running(&mut self, addr: impl ToIpAddr) -> bool {
+        self.inner.is_host_running(addr)
+    }
+
+    /// Partition two hosts, dropping all messages sent between them.
+    pub fn partition(&mut self, a: impl ToIpAddr, b: impl ToIpAddr) {
+        let (a, b) = (self.host_name(a), self.host_name(b));
+        self.inner.partition(&*a, &*b);
+        self.network.partition(&a, &b);
+        event::emit(EventKind::Partition { a, b });
+    }
+
+    /// Partition two hosts in one direction, dropping all messages sent from `from` to `to`.
+    pub fn partition_oneway(&mut self, from: impl ToIpAddr, to: impl ToIpAddr) {
+        let (from, to) = (self.host_name(from), self.host_name(to));
+        self.inner.partition_oneway(&*from, &*to);
+        self.network.partition_oneway(&from, &to);
+        event::emit(EventKind::PartitionOneway { from, to });
+    }
+
+    /// Repair the connection between two hosts, undoing a previous partition.
+    pub fn repair(&mut self, a: impl ToIpAddr, b: impl ToIpAddr) {
+        let (a, b) = (self.host_name(a), self.host_name(b));
+        self.inner.repair(&*a, &*b);
+        self.network.repair(&a, &b);
+        event::emit(EventKind::Repair { a, b });
+    }
+
+    /// Repair the connection from `from` to `to`, undoing a previous one-way partition.
+    pub fn repair_oneway(&mut self, from: impl ToIpAddr, to: impl ToIpAddr) {
+        let (from, to) = (self.host_name(from), self.host_name(to));
+        self.inner.repair_oneway(&*from, &*to);
+        self.network.repair_oneway(&from, &to);
+        event::emit(EventKind::RepairOneway { from, to });
+    }
+
+    /// Hold messages between two hosts until [`Sim::release`] is called.
+    pub fn hold(&mut self, a: impl ToIpAddr, b: impl ToIpAddr) {
+        let (a, b) = (self.host_name(a), self.host_name(b));
+        self.inner.hold(&*a, &*b);
+        self.network.hold(&a, &b);
+        event::emit(EventKind::Hold { a, b });
+    }
+
+    /// Release messages held between two hosts, delivering them immediately.
+    pub fn release(&mut self, a: impl ToIpAddr, b: impl ToIpAddr) {
+        let (a, b) = (self.host_name(a), self.host_name(b));
+        self.inner.release(&*a, &*b);
+        self.network.release(&a, &b);
+        event::emit(EventKind::Release { a, b });
+    }
+
+    /// Set the clock of a host.
+    ///
+    /// The host's wall clock jumps to the new offset immediately, forward or backward, and drifts
+    /// from there at the new rate. Its monotonic clock never jumps, only its rate changes.
+    pub fn set_clock(&mut self, addr: impl ToIpAddr, clock: Clock) {
+        let host = self.host_name(addr);
+        info!(host, ?clock, "clock set");
+
+        context::with(|ctx| {
+            let elapsed = ctx.elapsed;
+            ctx.clocks
+                .entry(host)
+                .or_insert_with(|| HostClock::new(elapsed))
+                .set(clock, elapsed);
+        });
+    }
+
+    /// Install a nemesis that injects faults while the simulation is stepped.
+    ///
+    /// This replaces any previously installed nemesis.
+    pub fn nemesis(&mut self, nemesis: Nemesis) {
+        self.nemesis = Some(nemesis);
+    }
+
+    /// Register an invariant that is checked after every simulation step.
+    ///
+    /// The check fails if the closure returns `false` or an error, in which case the failing
+    /// step returns an error that names the invariant, the simulated time and the RNG seed.
+    pub fn invariant<F, R>(&mut self, name: impl Into<String>, mut check: F)
+    where
+        F: FnMut(&Sim) -> R + 'static,
+        R: InvariantResult,
+    {
+        self.invariants.push(Invariant {
+            name: name.into(),
+            check: Box::new(move |sim| check(sim).into_result()),
+        });
+    }
+
+    /// Register a handler that observes every network message.
+    ///
+    /// Handlers are called after each step, for the messages sent, delivered, dropped or held
+    /// during that step.
+    pub fn on_message<F>(&mut self, handler: F)
+    where
+        F: FnMut(&MessageEvent) + 'static,
+    {
+        tap::enable();
+        self.message_handlers.push(Box::new(handler));
+    }
+
+    /// Configure the link between two hosts.
+    ///
+    /// Both hosts must already be registered with the simulation.
+    pub fn link(&mut self, a: impl ToIpAddr, b: impl ToIpAddr) -> Link<'_> {
+        let a = self.inner.lookup(a);
+        let b = self.inner.lookup(b);
+        Link {
+            sim: &mut self.inner,
+            a,
+            b,
+        }
+    }
+
+    /// Set an environment variable for a host, on top of the scene's environment.
+    ///
+    /// The variable is seen by the host's process from its next start on, and by all processes
+    /// that are not started yet.
+    pub fn set_env(&mut self, addr: impl ToIpAddr, key: &str, value: &str) {
+        let host = self.host_name(addr);
+        context::with(|ctx| ctx.env.set(&host, key, Some(value)));
+    }
+
+    /// Remove an environment variable for a host, even if the scene sets it.
+    ///
+    /// As with [`Sim::set_env`], this takes effect from the host's next start on.
+    pub fn remove_env(&mut self, addr: impl ToIpAddr, key: &str) {
+        let host = self.host_name(addr);
+        context::with(|ctx| ctx.env.set(&host, key, None));
+    }
+
+    /// Configure the faults injected into the disk of a host.
+    pub fn disk(&mut self, addr: impl ToIpAddr) -> Disk {
+        Disk {
+            host: self.host_name(addr),
+        }
     }
 
     pub fn step(&mut self) -> Result<bool> {
-        let res = self.0.step();
+        self.restart_due_hosts();
+
+        if let Some(mut nemesis) = self.nemesis.take() {
+            nemesis.tick(self);
+            self.nemesis = Some(nemesis);
+        }
 
-        let duration = self.0.since_epoch();
-        context::advance_time(duration);
+        let res = host::track(|| self.inner.step());
 
-        res
+        let duration = self.inner.since_epoch();
+        context::advance_time(duration, self.inner.elapsed());
+        green::run();
+
+        for message in tap::drain() {
+            for handler in &mut self.message_handlers {
+                handler(&message);
+            }
+        }
+
+        if let Some(reason) = context::take_failure() {
+            return Err(reason.into());
+        }
+        let finished = res?;
+        self.check_invariants()?;
+
+        Ok(finished)
     }
 
     pub fn run(&mut self) -> Result {
@@ -49,4 +287,301 @@ impl Sim {
 
         Ok(())
     }
+
+    /// Run the simulation for the given duration of simulated time.
+    ///
+    /// Returns early if all clients finish.
+    pub fn run_for(&mut self, duration: Duration) -> Result {
+        let until = self.elapsed() + duration;
+        while self.elapsed() < until {
+            if self.step_clients()? {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Run the simulation until the given condition holds.
+    ///
+    /// The condition is checked before every step, so no step is taken if it already holds. Fails
+    /// if all clients finish before the condition holds.
+    pub fn run_until<F>(&mut self, mut cond: F) -> Result
+    where
+        F: FnMut(&Sim) -> bool,
+    {
+        while !cond(self) {
+            if self.step_clients()? {
+                if cond(self) {
+                    break;
+                }
+                return Err("all clients finished before the condition held".into());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Run the simulation until no messages are in flight on the network.
+    ///
+    /// At least one step is always taken. Messages held on a link are not waited for, as they are
+    /// only delivered once the link is released. Returns early if all clients finish.
+    pub fn run_until_quiescent(&mut self) -> Result {
+        loop {
+            let finished = self.step_clients()?;
+            if finished || self.messages_in_flight() == 0 {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Step the simulation, returning whether all clients finished.
+    ///
+    /// Unlike [`Sim::step`], a simulation without clients is never finished, so hosts can be run
+    /// for a while on their own.
+    fn step_clients(&mut self) -> Result<bool> {
+        Ok(self.step()? && self.has_clients)
+    }
+
+    /// Restart crashed hosts whose restart time has been reached.
+    fn restart_due_hosts(&mut self) {
+        let now = self.elapsed();
+        let (due, pending): (Vec<_>, Vec<_>) =
+            self.restarts.drain(..).partition(|(at, _)| *at <= now);
+        self.restarts = pending;
+
+        for (_, host) in due {
+            self.inner.bounce(&*host);
+            event::emit(EventKind::Restart { host });
+        }
+    }
+
+    fn check_invariants(&mut self) -> Result {
+        let mut invariants = mem::take(&mut self.invariants);
+        let result = invariants.iter_mut().try_for_each(|inv| {
+            (inv.check)(self).map_err(|error| {
+                let name = &inv.name;
+                let time = self.elapsed();
+                let seed = context::with(|ctx| ctx.seed);
+                error!(invariant = name, ?time, seed, %error, "invariant violated");
+                format!("invariant `{name}` violated at {time:?} (seed {seed}): {error}").into()
+            })
+        });
+        self.invariants = invariants;
+
+        result
+    }
+
+    /// Number of messages in flight on links that are not held.
+    fn messages_in_flight(&self) -> usize {
+        let mut links = Vec::new();
+        self.inner.links(|iter| {
+            links = iter.map(|link| (link.pair(), link.count())).collect();
+        });
+        links
+            .into_iter()
+            .filter(|((a, b), _)| {
+                let held = link(&self.host_name(*a), &self.host_name(*b));
+                !self.network.held.contains(&held)
+            })
+            .map(|(_, count)| count)
+            .sum()
+    }
+
+    /// Drop the process state of a crashed host, and the changes it did not sync to disk.
+    fn crash_process(&self, host: &str) {
+        green::kill(host);
+        let stats = context::with(|ctx| {
+            ctx.env.reset(host);
+            ctx.vfs.crash(host, &mut ctx.rng)
+        });
+        if stats.lost > 0 || stats.torn > 0 {
+            info!(
+                host,
+                lost = stats.lost,
+                torn = stats.torn,
+                "unsynced writes lost"
+            );
+        }
+    }
+
+    fn host_name(&self, addr: impl ToIpAddr) -> String {
+        let ip = self.inner.lookup(addr);
+        self.inner
+            .reverse_lookup(ip)
+            .unwrap_or_else(|| ip.to_string())
+    }
+}
+
+impl Drop for Sim {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            self.network.report();
+        }
+    }
+}
+
+/// Result of an invariant check.
+pub trait InvariantResult {
+    fn into_result(self) -> Result;
+}
+
+impl InvariantResult for bool {
+    fn into_result(self) -> Result {
+        match self {
+            true => Ok(()),
+            false => Err("check returned false".into()),
+        }
+    }
+}
+
+impl InvariantResult for Result {
+    fn into_result(self) -> Result {
+        self
+    }
+}
+
+struct Invariant {
+    name: String,
+    check: Box<dyn FnMut(&Sim) -> Result>,
+}
+
+/// Handle for configuring the network link between two hosts.
+///
+/// Settings made through a `Link` override the scene-wide configuration for that link and stay in
+/// effect until changed again.
+///
+/// Dropping individual messages and limiting bandwidth are not supported, as turmoil has no way to
+/// act on a single message in flight. Use [`fail_rate`](Link::fail_rate) to make a link lossy.
+pub struct Link<'a> {
+    sim: &'a mut turmoil::Sim<'static>,
+    a: IpAddr,
+    b: IpAddr,
+}
+
+impl Link<'_> {
+    /// Set a fixed message latency, removing any variance.
+    pub fn latency(self, value: Duration) -> Self {
+        self.sim.set_link_latency(self.a, self.b, value);
+        self
+    }
+
+    /// Set the maximum message latency.
+    pub fn max_latency(self, value: Duration) -> Self {
+        self.sim.set_link_max_message_latency(self.a, self.b, value);
+        self
+    }
+
+    /// Set the probability of the link failing when a message is sent over it.
+    ///
+    /// A failed link drops messages until it is repaired again, according to the scene's
+    /// `repair_rate`.
+    pub fn fail_rate(self, value: f64) -> Self {
+        self.sim.set_link_fail_rate(self.a, self.b, value);
+        self
+    }
+}
+
+/// Handle for configuring the faults injected into the disk of a host.
+///
+/// Settings made through a `Disk` override the scene-wide configuration for that host and stay in
+/// effect until changed again, including across crashes. Injected faults are logged.
+pub struct Disk {
+    host: String,
+}
+
+impl Disk {
+    /// Set the probability of a read or write failing with `EIO`.
+    pub fn eio_rate(self, value: f64) -> Self {
+        self.update(|faults| faults.eio_rate = value)
+    }
+
+    /// Set the probability of a write failing with `ENOSPC`.
+    pub fn enospc_rate(self, value: f64) -> Self {
+        self.update(|faults| faults.enospc_rate = value)
+    }
+
+    /// Set the probability of a write failing with `EDQUOT`.
+    pub fn edquot_rate(self, value: f64) -> Self {
+        self.update(|faults| faults.edquot_rate = value)
+    }
+
+    /// Set the probability of a read or write transferring only part of its buffer.
+    pub fn short_io_rate(self, value: f64) -> Self {
+        self.update(|faults| faults.short_io_rate = value)
+    }
+
+    /// Set the time every read, write and sync takes.
+    ///
+    /// The latency advances the host's clocks, as a blocking call would.
+    pub fn latency(self, value: Duration) -> Self {
+        self.update(|faults| faults.latency = value)
+    }
+
+    fn update(self, f: impl FnOnce(&mut DiskFaults)) -> Self {
+        context::with(|ctx| f(&mut ctx.vfs.host(&self.host).faults));
+        self
+    }
+}
+
+/// Tracks the network faults that are currently active.
+#[derive(Default)]
+struct NetworkState {
+    /// Directed links on which messages are dropped.
+    partitioned: BTreeSet<(String, String)>,
+    /// Links on which messages are held.
+    held: BTreeSet<(String, String)>,
+}
+
+impl NetworkState {
+    fn partition(&mut self, a: &str, b: &str) {
+        self.partition_oneway(a, b);
+        self.partition_oneway(b, a);
+    }
+
+    fn partition_oneway(&mut self, from: &str, to: &str) {
+        self.partitioned.insert((from.into(), to.into()));
+    }
+
+    fn repair(&mut self, a: &str, b: &str) {
+        self.repair_oneway(a, b);
+        self.repair_oneway(b, a);
+    }
+
+    fn repair_oneway(&mut self, from: &str, to: &str) {
+        self.partitioned.remove(&(from.into(), to.into()));
+    }
+
+    fn hold(&mut self, a: &str, b: &str) {
+        self.held.insert(link(a, b));
+    }
+
+    fn release(&mut self, a: &str, b: &str) {
+        self.held.remove(&link(a, b));
+    }
+
+    /// Log the currently active network faults.
+    fn report(&self) {
+        let partitioned: Vec<_> = self
+            .partitioned
+            .iter()
+            .map(|(from, to)| format!("{from} -> {to}"))
+            .collect();
+        let held: Vec<_> = self
+            .held
+            .iter()
+            .map(|(a, b)| format!("{a} <-> {b}"))
+            .collect();
+
+        error!(?partitioned, ?held, "active network faults at failure");
+    }
+}
+
+/// Normalize an undirected link between two hosts.
+fn link(a: &str, b: &str) -> (String, String) {
+    if a <= b {
+        (a.into(), b.into())
+    } else {
+        (b.into(), a.into())
+    }
 }
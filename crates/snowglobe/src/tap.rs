@@ -0,0 +1,136 @@
+//! Observation of network messages.
+//!
+//! Turmoil doesn't expose hooks into its network, but it traces every message it sends, holds,
+//! drops and delivers. The tap layer turns these trace events into [`MessageEvent`]s. The traces
+//! are only enabled once messages are observed or captured. They are matched by their message
+//! strings, which the `sim::message_kinds` test scene pins.
+
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use snowglobe_proto::Message as _;
+use snowglobe_proto::{MessageEvent, MessageEventKind};
+use tracing::field::{Field, Visit};
+use tracing::subscriber::Interest;
+use tracing::{Event, Subscriber};
+use tracing_subscriber::Layer;
+use tracing_subscriber::filter;
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+
+use crate::context;
+
+/// Target of turmoil's traces.
+const TURMOIL: &str = "turmoil";
+
+thread_local! {
+    /// Whether turmoil's message traces are turned into events.
+    static ENABLED: Cell<bool> = const { Cell::new(false) };
+    /// Events not yet handed to the `Sim`.
+    static PENDING: RefCell<Vec<MessageEvent>> = const { RefCell::new(Vec::new()) };
+    /// Events captured for writing at the end of the run, if capturing is enabled.
+    static CAPTURE: RefCell<Option<Vec<MessageEvent>>> = const { RefCell::new(None) };
+}
+
+/// Create the tap layer.
+pub(crate) fn layer<S>() -> impl Layer<S>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    // Turmoil's traces are checked on every event, so they cost nothing while the tap is off.
+    let filter = filter::dynamic_filter_fn(|meta, _| meta.target() == TURMOIL && ENABLED.get())
+        .with_callsite_filter(|meta| {
+            if meta.target() == TURMOIL {
+                Interest::sometimes()
+            } else {
+                Interest::never()
+            }
+        });
+    TapLayer.with_filter(filter)
+}
+
+/// Start turning turmoil's message traces into events.
+pub(crate) fn enable() {
+    ENABLED.set(true);
+}
+
+/// Take all message events observed since the last call.
+pub(crate) fn drain() -> Vec<MessageEvent> {
+    PENDING.take()
+}
+
+/// Start capturing message events.
+pub(crate) fn start_capture() {
+    enable();
+    CAPTURE.set(Some(Vec::new()));
+}
+
+/// Write all captured message events to the file at the given path, as JSON lines.
+pub(crate) fn write_capture(path: &Path) -> io::Result<()> {
+    let events = CAPTURE.take().unwrap_or_default();
+
+    let mut file = BufWriter::new(File::create(path)?);
+    for event in events {
+        writeln!(file, "{}", event.serialize())?;
+    }
+    file.flush()
+}
+
+struct TapLayer;
+
+impl<S: Subscriber> Layer<S> for TapLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let mut visitor = MessageVisitor::default();
+        event.record(&mut visitor);
+
+        let kind = match visitor.message.as_str() {
+            "Send" => MessageEventKind::Sent,
+            "Delivered" => MessageEventKind::Delivered,
+            "Hold" => MessageEventKind::Held,
+            m if m.starts_with("Drop") => MessageEventKind::Dropped,
+            _ => return,
+        };
+        let (Some(src), Some(dst)) = (visitor.src, visitor.dst) else {
+            return;
+        };
+
+        let event = MessageEvent {
+            time: context::with(|ctx| ctx.elapsed),
+            kind,
+            src,
+            dst,
+            protocol: visitor.protocol.unwrap_or_default(),
+        };
+
+        CAPTURE.with_borrow_mut(|capture| {
+            if let Some(events) = capture {
+                events.push(event.clone());
+            }
+        });
+        PENDING.with_borrow_mut(|pending| pending.push(event));
+    }
+}
+
+#[derive(Default)]
+struct MessageVisitor {
+    message: String,
+    src: Option<String>,
+    dst: Option<String>,
+    protocol: Option<String>,
+}
+
+impl Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        let value = format!("{value:?}");
+        match field.name() {
+            "message" => self.message = value,
+            "src" => self.src = Some(value),
+            "dst" => self.dst = Some(value),
+            "protocol" => self.protocol = Some(value),
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,1186 @@
+//! In-memory filesystems of simulated hosts.
+//!
+//! Every host gets its own filesystem, created empty on first use. File descriptors handed out
+//! for it start at [`FD_BASE`], so they can be told apart from the process's real descriptors.
+//! Errors are reported as `errno` values.
+//!
+//! Like a real disk, the filesystem distinguishes between its current state and what is durable.
+//! Data written to a file is durable once the file is synced, and directory entries once their
+//! directory is synced. When a host crashes, changes that were not synced are lost or torn.
+//!
+//! Faults and latency can be injected into the I/O operations of each host, see [`DiskFaults`].
+//!
+//! The random number devices are provided to all hosts and to code running outside of them, see
+//! [`Device`].
+
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::mem;
+use std::rc::Rc;
+use std::time::Duration;
+
+use libc::{
+    EBADF, EDQUOT, EEXIST, EINVAL, EIO, EISDIR, ENOENT, ENOSPC, ENOTDIR, ENOTEMPTY, c_int, mode_t,
+};
+use rand::Rng;
+
+/// Lowest file descriptor used for virtual files.
+pub(crate) const FD_BASE: c_int = 1 << 30;
+
+/// Lowest file descriptor used for devices.
+pub(crate) const DEVICE_FD_BASE: c_int = FD_BASE + (1 << 29);
+
+/// Inode number of the root directory.
+const ROOT: u64 = 1;
+
+pub(crate) type FsResult<T> = Result<T, c_int>;
+
+/// The filesystems of all simulated hosts.
+#[derive(Default)]
+pub(crate) struct Vfs {
+    hosts: BTreeMap<String, HostFs>,
+    /// Faults injected into the disks of hosts that don't override them.
+    pub faults: DiskFaults,
+    devices: BTreeMap<c_int, OpenDevice>,
+    /// Open directory streams of all hosts, keyed by their address.
+    ///
+    /// Streams are not kept per host, so that they are recognized when used outside of the host
+    /// that opened them, like when a host is dropped.
+    dirs: BTreeMap<usize, DirStream>,
+}
+
+impl Vfs {
+    /// Get the filesystem of the given host.
+    pub fn host(&mut self, name: &str) -> &mut HostFs {
+        if !self.hosts.contains_key(name) {
+            self.hosts.insert(name.into(), HostFs::new(self.faults));
+        }
+        self.hosts.get_mut(name).unwrap()
+    }
+
+    /// Close all files a host has open, as happens when its process exits.
+    pub fn close_all(&mut self, name: &str) {
+        self.dirs.retain(|_, dir| dir.host != name);
+        if let Some(fs) = self.hosts.get_mut(name) {
+            fs.chdir_ino(ROOT);
+            let fds: Vec<_> = fs.fds.keys().copied().collect();
+            for fd in fds {
+                let _ = fs.close(fd);
+            }
+        }
+        self.devices
+            .retain(|_, dev| dev.host.as_deref() != Some(name));
+    }
+
+    /// Open a device, on behalf of the given host or outside of any host.
+    ///
+    /// Devices are not part of any host's filesystem, so their file descriptors stay valid when
+    /// they are used by a different host, as happens with descriptors opened during setup.
+    pub fn open_device(&mut self, host: Option<String>, device: Device, flags: c_int) -> c_int {
+        let fd = self.free_device_fd();
+        let flags = flags & (libc::O_ACCMODE | libc::O_APPEND);
+        self.devices.insert(
+            fd,
+            OpenDevice {
+                host,
+                device,
+                flags,
+            },
+        );
+        fd
+    }
+
+    /// Get the device open at a file descriptor.
+    pub fn device(&self, fd: c_int) -> FsResult<Device> {
+        self.devices.get(&fd).map(|dev| dev.device).ok_or(EBADF)
+    }
+
+    /// Get the `open` flags of an open device.
+    pub fn device_flags(&self, fd: c_int) -> FsResult<c_int> {
+        self.devices.get(&fd).map(|dev| dev.flags).ok_or(EBADF)
+    }
+
+    pub fn close_device(&mut self, fd: c_int) -> FsResult<()> {
+        self.devices.remove(&fd).map(|_| ()).ok_or(EBADF)
+    }
+
+    /// Duplicate a device file descriptor, like [`HostFs::dup`].
+    pub fn dup_device(&mut self, fd: c_int, new: Option<c_int>) -> FsResult<c_int> {
+        let device = self.devices.get(&fd).ok_or(EBADF)?.clone();
+        let new = match new {
+            Some(new) if new == fd => return Ok(fd),
+            Some(new) if !is_device_fd(new) => return Err(EBADF),
+            Some(new) => new,
+            None => self.free_device_fd(),
+        };
+        self.devices.insert(new, device);
+        Ok(new)
+    }
+
+    fn free_device_fd(&self) -> c_int {
+        (DEVICE_FD_BASE..)
+            .find(|fd| !self.devices.contains_key(fd))
+            .unwrap()
+    }
+
+    /// Open a directory stream on behalf of a host, returning its address.
+    pub fn opendir(&mut self, host: &str, base: Base, path: &[u8]) -> FsResult<usize> {
+        let opts = OpenOptions {
+            read: true,
+            directory: true,
+            ..Default::default()
+        };
+        let fd = self.host(host).open(base, path, opts, 0, Duration::ZERO)?;
+        self.fdopendir(host, fd)
+    }
+
+    /// Open a directory stream for a directory file descriptor of a host, returning its address.
+    pub fn fdopendir(&mut self, host: &str, fd: c_int) -> FsResult<usize> {
+        let entries = self.host(host).dir_entries(fd)?;
+        let stream = DirStream {
+            host: host.into(),
+            fd,
+            entries,
+            pos: 0,
+            dirent: Box::new(unsafe { std::mem::zeroed() }),
+        };
+        let addr = &*stream.dirent as *const _ as usize;
+        self.dirs.insert(addr, stream);
+        Ok(addr)
+    }
+
+    pub fn dir_stream(&mut self, addr: usize) -> Option<&mut DirStream> {
+        self.dirs.get_mut(&addr)
+    }
+
+    /// Close a directory stream, along with the file descriptor of the host that opened it.
+    pub fn closedir(&mut self, addr: usize) -> FsResult<()> {
+        let stream = self.dirs.remove(&addr).ok_or(EBADF)?;
+        self.host(&stream.host).close(stream.fd)
+    }
+
+    /// Crash a host, closing all its files and losing the changes it did not sync.
+    pub fn crash(&mut self, name: &str, rng: &mut impl Rng) -> CrashStats {
+        self.close_all(name);
+        match self.hosts.get_mut(name) {
+            Some(fs) => fs.crash(rng),
+            None => CrashStats::default(),
+        }
+    }
+}
+
+/// Unsynced changes affected by a crash.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct CrashStats {
+    /// Changes that were lost entirely.
+    pub lost: usize,
+    /// Writes of which only a prefix made it to disk.
+    pub torn: usize,
+}
+
+/// Faults injected into the I/O operations of a host's disk.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct DiskFaults {
+    /// Probability of a read or write failing with `EIO`.
+    pub eio_rate: f64,
+    /// Probability of a write failing with `ENOSPC`.
+    pub enospc_rate: f64,
+    /// Probability of a write failing with `EDQUOT`.
+    pub edquot_rate: f64,
+    /// Probability of a read or write transferring only part of its buffer.
+    pub short_io_rate: f64,
+    /// Time every read, write and sync takes.
+    pub latency: Duration,
+}
+
+/// An I/O operation that faults can be injected into.
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum Io {
+    Read,
+    Write,
+    Sync,
+}
+
+/// A fault injected into an I/O operation.
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum DiskFault {
+    /// The operation fails with the given `errno`.
+    Error(c_int),
+    /// The operation transfers only the given number of bytes.
+    Short(usize),
+}
+
+impl DiskFaults {
+    /// Draw the fault to inject into an operation transferring `len` bytes, if any.
+    pub fn draw(&self, io: Io, len: usize, rng: &mut impl Rng) -> Option<DiskFault> {
+        // Rates of zero don't consume randomness, so scenes without faults are unaffected.
+        let mut chance = |rate: f64| rate > 0. && rng.random_bool(rate.min(1.));
+
+        match io {
+            Io::Sync => return None,
+            _ if chance(self.eio_rate) => return Some(DiskFault::Error(EIO)),
+            Io::Write if chance(self.enospc_rate) => return Some(DiskFault::Error(ENOSPC)),
+            Io::Write if chance(self.edquot_rate) => return Some(DiskFault::Error(EDQUOT)),
+            _ => {}
+        }
+        if len > 1 && chance(self.short_io_rate) {
+            return Some(DiskFault::Short(rng.random_range(1..len)));
+        }
+        None
+    }
+}
+
+/// Whether a file descriptor refers to a virtual file or device.
+pub(crate) fn is_virtual_fd(fd: c_int) -> bool {
+    fd >= FD_BASE
+}
+
+/// Whether a file descriptor refers to a device.
+pub(crate) fn is_device_fd(fd: c_int) -> bool {
+    fd >= DEVICE_FD_BASE
+}
+
+/// A character device whose reads draw from the simulation RNG.
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum Device {
+    Random,
+    Urandom,
+}
+
+impl Device {
+    /// Get the device at an absolute path.
+    pub fn from_path(path: &[u8]) -> Option<Self> {
+        match path {
+            b"/dev/random" => Some(Self::Random),
+            b"/dev/urandom" => Some(Self::Urandom),
+            _ => None,
+        }
+    }
+
+    /// The device number, as reported by `stat`.
+    pub fn rdev(self) -> libc::dev_t {
+        match self {
+            Self::Random => libc::makedev(1, 8),
+            Self::Urandom => libc::makedev(1, 9),
+        }
+    }
+}
+
+#[derive(Clone)]
+struct OpenDevice {
+    /// The host that opened the device, if any.
+    host: Option<String>,
+    device: Device,
+    flags: c_int,
+}
+
+/// The filesystem of a single host.
+pub(crate) struct HostFs {
+    inodes: BTreeMap<u64, Inode>,
+    next_ino: u64,
+    fds: BTreeMap<c_int, OpenFile>,
+    next_fd: c_int,
+    /// Inode of the working directory.
+    cwd: u64,
+    pub faults: DiskFaults,
+}
+
+pub(crate) struct Inode {
+    pub ino: u64,
+    pub kind: InodeKind,
+    /// Number of directory entries referring to the inode.
+    pub nlink: u32,
+    /// Permission bits.
+    pub mode: mode_t,
+    /// Time of the last modification, since the Unix epoch.
+    pub mtime: Duration,
+    synced: Synced,
+}
+
+pub(crate) enum InodeKind {
+    File(Vec<u8>),
+    Dir {
+        parent: u64,
+        entries: BTreeMap<Vec<u8>, u64>,
+    },
+}
+
+/// The durable state of an inode, and the changes made to it since it was last synced.
+enum Synced {
+    File {
+        data: Vec<u8>,
+        pending: Vec<FileOp>,
+    },
+    Dir {
+        entries: BTreeMap<Vec<u8>, u64>,
+        pending: Vec<DirOp>,
+    },
+}
+
+enum FileOp {
+    Write { offset: u64, data: Vec<u8> },
+    Truncate(u64),
+}
+
+enum DirOp {
+    Link(Vec<u8>, u64),
+    Unlink(Vec<u8>),
+    /// A rename within the directory, which is atomic.
+    Rename {
+        from: Vec<u8>,
+        to: Vec<u8>,
+        ino: u64,
+    },
+}
+
+impl Synced {
+    fn new(kind: &InodeKind) -> Self {
+        match kind {
+            InodeKind::File(data) => Synced::File {
+                data: data.clone(),
+                pending: Vec::new(),
+            },
+            InodeKind::Dir { entries, .. } => Synced::Dir {
+                entries: entries.clone(),
+                pending: Vec::new(),
+            },
+        }
+    }
+
+    fn log_file(&mut self, op: FileOp) {
+        if let Synced::File { pending, .. } = self {
+            pending.push(op);
+        }
+    }
+
+    fn log_dir(&mut self, op: DirOp) {
+        if let Synced::Dir { pending, .. } = self {
+            pending.push(op);
+        }
+    }
+
+    /// Whether a durable directory entry refers to the given inode.
+    fn links(&self, ino: u64) -> bool {
+        match self {
+            Synced::Dir { entries, .. } => entries.values().any(|i| *i == ino),
+            Synced::File { .. } => false,
+        }
+    }
+}
+
+/// Write `buf` into `data` at `offset`, growing it as needed.
+fn write_bytes(data: &mut Vec<u8>, offset: u64, buf: &[u8]) {
+    let start = offset as usize;
+    let end = start + buf.len();
+    if data.len() < end {
+        data.resize(end, 0);
+    }
+    data[start..end].copy_from_slice(buf);
+}
+
+impl Inode {
+    pub fn is_dir(&self) -> bool {
+        matches!(self.kind, InodeKind::Dir { .. })
+    }
+
+    pub fn size(&self) -> u64 {
+        match &self.kind {
+            InodeKind::File(data) => data.len() as u64,
+            InodeKind::Dir { entries, .. } => entries.len() as u64,
+        }
+    }
+
+    fn entries(&self) -> FsResult<&BTreeMap<Vec<u8>, u64>> {
+        match &self.kind {
+            InodeKind::Dir { entries, .. } => Ok(entries),
+            InodeKind::File(_) => Err(ENOTDIR),
+        }
+    }
+
+    fn entries_mut(&mut self) -> FsResult<&mut BTreeMap<Vec<u8>, u64>> {
+        match &mut self.kind {
+            InodeKind::Dir { entries, .. } => Ok(entries),
+            InodeKind::File(_) => Err(ENOTDIR),
+        }
+    }
+}
+
+/// A file descriptor of an open file.
+#[derive(Clone)]
+struct OpenFile {
+    ino: u64,
+    /// The file offset, shared with the descriptors duplicated from this one.
+    offset: Rc<Cell<u64>>,
+    readable: bool,
+    writable: bool,
+    append: bool,
+    /// Whether writes are synced immediately, as with `O_SYNC`.
+    sync: bool,
+}
+
+/// How to open a file, decoded from `open` flags.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct OpenOptions {
+    pub read: bool,
+    pub write: bool,
+    pub append: bool,
+    pub create: bool,
+    pub exclusive: bool,
+    pub truncate: bool,
+    pub directory: bool,
+    pub sync: bool,
+}
+
+impl OpenOptions {
+    pub fn from_flags(flags: c_int) -> Self {
+        let access = flags & libc::O_ACCMODE;
+        Self {
+            read: access == libc::O_RDONLY || access == libc::O_RDWR,
+            write: access == libc::O_WRONLY || access == libc::O_RDWR,
+            append: flags & libc::O_APPEND != 0,
+            create: flags & libc::O_CREAT != 0,
+            exclusive: flags & libc::O_EXCL != 0,
+            truncate: flags & libc::O_TRUNC != 0,
+            directory: flags & libc::O_DIRECTORY != 0,
+            sync: flags & (libc::O_SYNC | libc::O_DSYNC) != 0,
+        }
+    }
+}
+
+/// An entry returned when reading a directory.
+pub(crate) struct DirEntry {
+    pub ino: u64,
+    pub name: Vec<u8>,
+    pub is_dir: bool,
+}
+
+/// Directory entry type returned by `readdir`.
+#[cfg(target_os = "linux")]
+pub(crate) type Dirent = libc::dirent64;
+#[cfg(target_os = "macos")]
+pub(crate) type Dirent = libc::dirent;
+
+/// An open directory stream, as returned by `opendir`.
+pub(crate) struct DirStream {
+    /// The host that opened the stream.
+    host: String,
+    pub fd: c_int,
+    pub entries: Vec<DirEntry>,
+    pub pos: usize,
+    /// Buffer the current entry is returned in.
+    pub dirent: Box<Dirent>,
+}
+
+/// Where to resolve a path from.
+#[derive(Clone, Copy)]
+pub(crate) enum Base {
+    /// The working directory.
+    Cwd,
+    /// The directory open at the given file descriptor.
+    Fd(c_int),
+}
+
+impl HostFs {
+    fn new(faults: DiskFaults) -> Self {
+        let kind = InodeKind::Dir {
+            parent: ROOT,
+            entries: BTreeMap::new(),
+        };
+        let root = Inode {
+            ino: ROOT,
+            synced: Synced::new(&kind),
+            kind,
+            nlink: 1,
+            mode: 0o755,
+            mtime: Duration::ZERO,
+        };
+
+        Self {
+            inodes: BTreeMap::from([(ROOT, root)]),
+            next_ino: ROOT + 1,
+            fds: BTreeMap::new(),
+            next_fd: FD_BASE,
+            cwd: ROOT,
+            faults,
+        }
+    }
+
+    pub fn open(
+        &mut self,
+        base: Base,
+        path: &[u8],
+        opts: OpenOptions,
+        mode: mode_t,
+        now: Duration,
+    ) -> FsResult<c_int> {
+        let existing = match self.resolve_parent(base, path) {
+            Ok((parent, name)) => self.lookup(parent, name)?.ok_or(parent),
+            // The root directory has no parent.
+            Err(EEXIST) => Ok(ROOT),
+            Err(errno) => return Err(errno),
+        };
+
+        let ino = match existing {
+            Ok(_) if opts.create && opts.exclusive => return Err(EEXIST),
+            Ok(ino) => ino,
+            Err(parent) if opts.create => {
+                let (_, name) = self.resolve_parent(base, path)?;
+                let kind = InodeKind::File(Vec::new());
+                self.create(parent, name, kind, mode, now)?
+            }
+            Err(_) => return Err(ENOENT),
+        };
+
+        let inode = &mut self.inodes.get_mut(&ino).unwrap();
+        if inode.is_dir() && opts.write {
+            return Err(EISDIR);
+        }
+        if !inode.is_dir() && opts.directory {
+            return Err(ENOTDIR);
+        }
+        if opts.truncate && opts.write {
+            if let InodeKind::File(data) = &mut inode.kind {
+                data.clear();
+                inode.synced.log_file(FileOp::Truncate(0));
+            }
+            inode.mtime = now;
+        }
+
+        let fd = self.next_fd;
+        self.next_fd += 1;
+        self.fds.insert(
+            fd,
+            OpenFile {
+                ino,
+                offset: Rc::default(),
+                readable: opts.read,
+                writable: opts.write,
+                append: opts.append,
+                sync: opts.sync,
+            },
+        );
+        Ok(fd)
+    }
+
+    pub fn close(&mut self, fd: c_int) -> FsResult<()> {
+        let file = self.fds.remove(&fd).ok_or(EBADF)?;
+        self.release(file.ino);
+        Ok(())
+    }
+
+    /// Read from the file at its current offset.
+    pub fn read(&mut self, fd: c_int, buf: &mut [u8]) -> FsResult<usize> {
+        let file = self.fds.get(&fd).ok_or(EBADF)?;
+        let n = self.read_at(fd, buf, file.offset.get())?;
+        file.offset.set(file.offset.get() + n as u64);
+        Ok(n)
+    }
+
+    pub fn read_at(&self, fd: c_int, buf: &mut [u8], offset: u64) -> FsResult<usize> {
+        let file = self.fds.get(&fd).ok_or(EBADF)?;
+        if !file.readable {
+            return Err(EBADF);
+        }
+
+        let data = match &self.inodes[&file.ino].kind {
+            InodeKind::File(data) => data,
+            InodeKind::Dir { .. } => return Err(EISDIR),
+        };
+
+        let start = (offset as usize).min(data.len());
+        let n = buf.len().min(data.len() - start);
+        buf[..n].copy_from_slice(&data[start..start + n]);
+        Ok(n)
+    }
+
+    /// Write to the file at its current offset, or at its end if it was opened for appending.
+    pub fn write(&mut self, fd: c_int, buf: &[u8], now: Duration) -> FsResult<usize> {
+        let file = self.fds.get(&fd).ok_or(EBADF)?;
+        let pos = file.offset.clone();
+        let offset = match file.append {
+            true => self.inodes[&file.ino].size(),
+            false => pos.get(),
+        };
+
+        let n = self.write_at(fd, buf, offset, now)?;
+        pos.set(offset + n as u64);
+        Ok(n)
+    }
+
+    pub fn write_at(
+        &mut self,
+        fd: c_int,
+        buf: &[u8],
+        offset: u64,
+        now: Duration,
+    ) -> FsResult<usize> {
+        let file = self.fds.get(&fd).ok_or(EBADF)?;
+        if !file.writable {
+            return Err(EBADF);
+        }
+
+        let (ino, sync) = (file.ino, file.sync);
+        let inode = self.inodes.get_mut(&ino).unwrap();
+        let InodeKind::File(data) = &mut inode.kind else {
+            return Err(EISDIR);
+        };
+
+        write_bytes(data, offset, buf);
+        if !buf.is_empty() {
+            inode.synced.log_file(FileOp::Write {
+                offset,
+                data: buf.into(),
+            });
+        }
+        inode.mtime = now;
+        if sync {
+            self.sync_inode(ino);
+        }
+        Ok(buf.len())
+    }
+
+    pub fn seek(&mut self, fd: c_int, offset: i64, whence: c_int) -> FsResult<u64> {
+        let file = self.fds.get(&fd).ok_or(EBADF)?;
+        let base = match whence {
+            libc::SEEK_SET => 0,
+            libc::SEEK_CUR => file.offset.get() as i64,
+            libc::SEEK_END => self.inodes[&file.ino].size() as i64,
+            _ => return Err(EINVAL),
+        };
+
+        let new = base.checked_add(offset).filter(|o| *o >= 0).ok_or(EINVAL)?;
+        file.offset.set(new as u64);
+        Ok(new as u64)
+    }
+
+    pub fn truncate(&mut self, fd: c_int, len: u64, now: Duration) -> FsResult<()> {
+        let file = self.fds.get(&fd).ok_or(EBADF)?;
+        if !file.writable {
+            return Err(EINVAL);
+        }
+
+        let inode = self.inodes.get_mut(&file.ino).unwrap();
+        let InodeKind::File(data) = &mut inode.kind else {
+            return Err(EISDIR);
+        };
+        data.resize(len as usize, 0);
+        inode.synced.log_file(FileOp::Truncate(len));
+        inode.mtime = now;
+        Ok(())
+    }
+
+    /// Make the contents of an open file durable, or the entries of an open directory.
+    pub fn sync(&mut self, fd: c_int) -> FsResult<()> {
+        let file = self.fds.get(&fd).ok_or(EBADF)?;
+        self.sync_inode(file.ino);
+        Ok(())
+    }
+
+    /// Make all changes to the filesystem durable.
+    pub fn sync_all(&mut self) {
+        let inodes: Vec<_> = self.inodes.keys().copied().collect();
+        for ino in inodes {
+            self.sync_inode(ino);
+        }
+    }
+
+    /// Simulate a power loss.
+    ///
+    /// Each unsynced write to a file is kept, lost or torn, as chosen by `rng`. Each directory
+    /// keeps a prefix of its unsynced changes, as a journal would. The directory tree is then
+    /// repaired, as `fsck` would: entries referring to lost inodes or to directories linked
+    /// elsewhere are removed, and inodes that are no longer reachable are dropped.
+    fn crash(&mut self, rng: &mut impl Rng) -> CrashStats {
+        let mut stats = CrashStats::default();
+        for inode in self.inodes.values_mut() {
+            match (&mut inode.kind, &mut inode.synced) {
+                (InodeKind::File(current), Synced::File { data, pending }) => {
+                    for op in pending.drain(..) {
+                        match op {
+                            FileOp::Write { offset, data: buf } => match rng.random_range(0..3) {
+                                0 => write_bytes(data, offset, &buf),
+                                1 => stats.lost += 1,
+                                _ => {
+                                    let len = rng.random_range(0..buf.len());
+                                    write_bytes(data, offset, &buf[..len]);
+                                    stats.torn += 1;
+                                }
+                            },
+                            FileOp::Truncate(len) if rng.random_bool(0.5) => {
+                                data.resize(len as usize, 0);
+                            }
+                            FileOp::Truncate(_) => stats.lost += 1,
+                        }
+                    }
+                    current.clone_from(data);
+                }
+                (
+                    InodeKind::Dir {
+                        entries: current, ..
+                    },
+                    Synced::Dir { entries, pending },
+                ) => {
+                    let kept = rng.random_range(0..=pending.len());
+                    stats.lost += pending.len() - kept;
+                    for op in pending.drain(..).take(kept) {
+                        match op {
+                            DirOp::Link(name, ino) => entries.insert(name, ino),
+                            DirOp::Unlink(name) => entries.remove(&name),
+                            DirOp::Rename { from, to, ino } => {
+                                entries.remove(&from);
+                                entries.insert(to, ino)
+                            }
+                        };
+                    }
+                    current.clone_from(entries);
+                }
+                _ => unreachable!("inode changed type"),
+            }
+        }
+
+        self.repair();
+        stats
+    }
+
+    /// Rebuild the directory tree from the root after a crash.
+    fn repair(&mut self) {
+        let mut nlink = BTreeMap::from([(ROOT, 1)]);
+        let mut queue = vec![ROOT];
+        while let Some(dir) = queue.pop() {
+            let entries = mem::take(self.inodes.get_mut(&dir).unwrap().entries_mut().unwrap());
+            let mut kept = BTreeMap::new();
+            for (name, ino) in entries {
+                let Some(inode) = self.inodes.get_mut(&ino) else {
+                    continue;
+                };
+                if let InodeKind::Dir { parent, .. } = &mut inode.kind {
+                    if nlink.contains_key(&ino) {
+                        continue;
+                    }
+                    *parent = dir;
+                    queue.push(ino);
+                }
+                *nlink.entry(ino).or_insert(0) += 1;
+                kept.insert(name, ino);
+            }
+
+            let inode = self.inodes.get_mut(&dir).unwrap();
+            inode.synced = Synced::Dir {
+                entries: kept.clone(),
+                pending: Vec::new(),
+            };
+            *inode.entries_mut().unwrap() = kept;
+        }
+
+        self.inodes.retain(|ino, inode| match nlink.get(ino) {
+            Some(n) => {
+                inode.nlink = *n;
+                true
+            }
+            None => false,
+        });
+    }
+
+    /// Duplicate a file descriptor, sharing its offset.
+    ///
+    /// The duplicate gets a new descriptor, or `new` if given, closing the file open there.
+    /// Duplicating a descriptor onto itself does nothing.
+    pub fn dup(&mut self, fd: c_int, new: Option<c_int>) -> FsResult<c_int> {
+        let file = self.fds.get(&fd).ok_or(EBADF)?.clone();
+        let new = match new {
+            Some(new) if new == fd => return Ok(fd),
+            Some(new) if !(FD_BASE..DEVICE_FD_BASE).contains(&new) => return Err(EBADF),
+            Some(new) => {
+                let _ = self.close(new);
+                self.next_fd = self.next_fd.max(new + 1);
+                new
+            }
+            None => {
+                self.next_fd += 1;
+                self.next_fd - 1
+            }
+        };
+        self.fds.insert(new, file);
+        Ok(new)
+    }
+
+    /// Check that a file descriptor is open.
+    pub fn check_fd(&self, fd: c_int) -> FsResult<()> {
+        self.fds.get(&fd).map(|_| ()).ok_or(EBADF)
+    }
+
+    /// Get the `open` flags of an open file.
+    pub fn status_flags(&self, fd: c_int) -> FsResult<c_int> {
+        let file = self.fds.get(&fd).ok_or(EBADF)?;
+        let access = match (file.readable, file.writable) {
+            (true, true) => libc::O_RDWR,
+            (false, true) => libc::O_WRONLY,
+            _ => libc::O_RDONLY,
+        };
+        let append = if file.append { libc::O_APPEND } else { 0 };
+        let sync = if file.sync { libc::O_SYNC } else { 0 };
+        Ok(access | append | sync)
+    }
+
+    /// Get the inode of an open file.
+    pub fn fstat(&self, fd: c_int) -> FsResult<&Inode> {
+        let file = self.fds.get(&fd).ok_or(EBADF)?;
+        Ok(&self.inodes[&file.ino])
+    }
+
+    /// Get the inode at a path.
+    pub fn stat(&self, base: Base, path: &[u8]) -> FsResult<&Inode> {
+        let ino = self.resolve(base, path)?;
+        Ok(&self.inodes[&ino])
+    }
+
+    /// Change the working directory.
+    pub fn chdir(&mut self, base: Base, path: &[u8]) -> FsResult<()> {
+        let ino = self.resolve(base, path)?;
+        self.inodes[&ino].entries()?;
+        self.chdir_ino(ino);
+        Ok(())
+    }
+
+    fn chdir_ino(&mut self, ino: u64) {
+        let old = mem::replace(&mut self.cwd, ino);
+        self.release(old);
+    }
+
+    /// Get the absolute path of the working directory.
+    pub fn getcwd(&self) -> FsResult<Vec<u8>> {
+        self.dir_path(self.cwd)
+    }
+
+    /// Get the absolute path of an existing file, without any `.` or `..` components.
+    pub fn realpath(&self, base: Base, path: &[u8]) -> FsResult<Vec<u8>> {
+        let ino = self.resolve(base, path)?;
+        if self.inodes[&ino].is_dir() {
+            return self.dir_path(ino);
+        }
+
+        let (parent, name) = self.resolve_parent(base, path)?;
+        let mut path = self.dir_path(parent)?;
+        if parent != ROOT {
+            path.push(b'/');
+        }
+        path.extend_from_slice(name);
+        Ok(path)
+    }
+
+    pub fn mkdir(&mut self, base: Base, path: &[u8], mode: mode_t, now: Duration) -> FsResult<()> {
+        let (parent, name) = self.resolve_parent(base, path)?;
+        if self.lookup(parent, name)?.is_some() {
+            return Err(EEXIST);
+        }
+
+        let kind = InodeKind::Dir {
+            parent,
+            entries: BTreeMap::new(),
+        };
+        self.create(parent, name, kind, mode, now)?;
+        Ok(())
+    }
+
+    pub fn unlink(&mut self, base: Base, path: &[u8], now: Duration) -> FsResult<()> {
+        let (parent, name) = self.resolve_parent(base, path)?;
+        let ino = self.lookup(parent, name)?.ok_or(ENOENT)?;
+        if self.inodes[&ino].is_dir() {
+            return Err(EISDIR);
+        }
+
+        self.remove_entry(parent, name, now);
+        Ok(())
+    }
+
+    pub fn rmdir(&mut self, base: Base, path: &[u8], now: Duration) -> FsResult<()> {
+        let (parent, name) = self.resolve_parent(base, path)?;
+        let ino = self.lookup(parent, name)?.ok_or(ENOENT)?;
+        if !self.inodes[&ino].entries()?.is_empty() {
+            return Err(ENOTEMPTY);
+        }
+
+        self.remove_entry(parent, name, now);
+        Ok(())
+    }
+
+    pub fn rename(
+        &mut self,
+        from_base: Base,
+        from: &[u8],
+        to_base: Base,
+        to: &[u8],
+        now: Duration,
+    ) -> FsResult<()> {
+        let (from_parent, from_name) = self.resolve_parent(from_base, from)?;
+        let (to_parent, to_name) = self.resolve_parent(to_base, to)?;
+        let ino = self.lookup(from_parent, from_name)?.ok_or(ENOENT)?;
+        let is_dir = self.inodes[&ino].is_dir();
+
+        let replaced = self.lookup(to_parent, to_name)?;
+        if let Some(existing) = replaced {
+            if existing == ino {
+                return Ok(());
+            }
+            match (is_dir, &self.inodes[&existing].kind) {
+                (false, InodeKind::Dir { .. }) => return Err(EISDIR),
+                (true, InodeKind::File(_)) => return Err(ENOTDIR),
+                (true, InodeKind::Dir { entries, .. }) if !entries.is_empty() => {
+                    return Err(ENOTEMPTY);
+                }
+                _ => {}
+            }
+        }
+
+        if is_dir && self.is_ancestor(ino, to_parent) {
+            return Err(EINVAL);
+        }
+
+        // The entry of a replaced inode is overwritten, so that a crash can never leave the
+        // target name missing.
+        let from_dir = self.inodes.get_mut(&from_parent).unwrap();
+        from_dir.entries_mut()?.remove(from_name);
+        if from_parent == to_parent {
+            from_dir.entries_mut()?.insert(to_name.into(), ino);
+            from_dir.synced.log_dir(DirOp::Rename {
+                from: from_name.into(),
+                to: to_name.into(),
+                ino,
+            });
+        } else {
+            from_dir.synced.log_dir(DirOp::Unlink(from_name.into()));
+            let to_dir = self.inodes.get_mut(&to_parent).unwrap();
+            to_dir.entries_mut()?.insert(to_name.into(), ino);
+            to_dir.synced.log_dir(DirOp::Link(to_name.into(), ino));
+        }
+        if let Some(existing) = replaced {
+            self.inodes.get_mut(&existing).unwrap().nlink -= 1;
+            self.release(existing);
+        }
+        if let InodeKind::Dir { parent, .. } = &mut self.inodes.get_mut(&ino).unwrap().kind {
+            *parent = to_parent;
+        }
+        self.inodes.get_mut(&from_parent).unwrap().mtime = now;
+        self.inodes.get_mut(&to_parent).unwrap().mtime = now;
+        Ok(())
+    }
+
+    /// List the entries of an open directory, including `.` and `..`.
+    fn dir_entries(&self, fd: c_int) -> FsResult<Vec<DirEntry>> {
+        let inode = self.fstat(fd)?;
+        let InodeKind::Dir { parent, entries } = &inode.kind else {
+            return Err(ENOTDIR);
+        };
+
+        let mut list = vec![
+            DirEntry {
+                ino: inode.ino,
+                name: b".".into(),
+                is_dir: true,
+            },
+            DirEntry {
+                ino: *parent,
+                name: b"..".into(),
+                is_dir: true,
+            },
+        ];
+        for (name, ino) in entries {
+            list.push(DirEntry {
+                ino: *ino,
+                name: name.clone(),
+                is_dir: self.inodes[ino].is_dir(),
+            });
+        }
+        Ok(list)
+    }
+
+    fn create(
+        &mut self,
+        parent: u64,
+        name: &[u8],
+        kind: InodeKind,
+        mode: mode_t,
+        now: Duration,
+    ) -> FsResult<u64> {
+        let ino = self.next_ino;
+        self.next_ino += 1;
+
+        let dir = self.inodes.get_mut(&parent).unwrap();
+        dir.entries_mut()?.insert(name.into(), ino);
+        dir.synced.log_dir(DirOp::Link(name.into(), ino));
+        dir.mtime = now;
+
+        let inode = Inode {
+            ino,
+            synced: Synced::new(&kind),
+            kind,
+            nlink: 1,
+            mode: mode & 0o7777,
+            mtime: now,
+        };
+        self.inodes.insert(ino, inode);
+        Ok(ino)
+    }
+
+    /// Remove a directory entry.
+    fn remove_entry(&mut self, parent: u64, name: &[u8], now: Duration) {
+        let dir = self.inodes.get_mut(&parent).unwrap();
+        let Ok(Some(ino)) = dir.entries_mut().map(|e| e.remove(name)) else {
+            return;
+        };
+        dir.synced.log_dir(DirOp::Unlink(name.into()));
+        dir.mtime = now;
+
+        self.inodes.get_mut(&ino).unwrap().nlink -= 1;
+        self.release(ino);
+    }
+
+    /// Drop an inode once no directory entry, no file descriptor and not the working directory
+    /// refers to it anymore.
+    ///
+    /// An inode that is still linked from the durable state of a directory is kept, as a crash
+    /// could bring the link back.
+    fn release(&mut self, ino: u64) {
+        let unlinked = self.inodes.get(&ino).is_some_and(|i| i.nlink == 0);
+        if unlinked
+            && ino != self.cwd
+            && !self.fds.values().any(|f| f.ino == ino)
+            && !self.inodes.values().any(|i| i.synced.links(ino))
+        {
+            self.inodes.remove(&ino);
+        }
+    }
+
+    fn sync_inode(&mut self, ino: u64) {
+        let inode = self.inodes.get_mut(&ino).unwrap();
+        let unlinked = match (&inode.kind, &mut inode.synced) {
+            (InodeKind::File(current), Synced::File { data, pending }) => {
+                data.clone_from(current);
+                pending.clear();
+                Vec::new()
+            }
+            (
+                InodeKind::Dir {
+                    entries: current, ..
+                },
+                Synced::Dir { entries, pending },
+            ) => {
+                pending.clear();
+                mem::replace(entries, current.clone())
+                    .into_values()
+                    .collect()
+            }
+            _ => unreachable!("inode changed type"),
+        };
+
+        // Inodes that were only kept for their durable links may be gone now.
+        for ino in unlinked {
+            self.release(ino);
+        }
+    }
+
+    /// Get the absolute path of a directory, which fails if it was removed.
+    fn dir_path(&self, mut ino: u64) -> FsResult<Vec<u8>> {
+        let mut names = Vec::new();
+        while ino != ROOT {
+            let InodeKind::Dir { parent, .. } = self.inodes[&ino].kind else {
+                return Err(ENOTDIR);
+            };
+            let entries = self.inodes[&parent].entries()?;
+            let (name, _) = entries.iter().find(|(_, i)| **i == ino).ok_or(ENOENT)?;
+            names.push(name);
+            ino = parent;
+        }
+
+        let mut path = Vec::new();
+        for name in names.iter().rev() {
+            path.push(b'/');
+            path.extend_from_slice(name);
+        }
+        if path.is_empty() {
+            path.push(b'/');
+        }
+        Ok(path)
+    }
+
+    fn lookup(&self, dir: u64, name: &[u8]) -> FsResult<Option<u64>> {
+        let entries = self.inodes[&dir].entries()?;
+        Ok(entries.get(name).copied())
+    }
+
+    fn is_ancestor(&self, ancestor: u64, mut ino: u64) -> bool {
+        loop {
+            if ino == ancestor {
+                return true;
+            }
+            match &self.inodes[&ino].kind {
+                InodeKind::Dir { parent, .. } if *parent != ino => ino = *parent,
+                _ => return false,
+            }
+        }
+    }
+
+    fn base_ino(&self, base: Base, path: &[u8]) -> FsResult<u64> {
+        match base {
+            _ if path.starts_with(b"/") => Ok(ROOT),
+            Base::Cwd => Ok(self.cwd),
+            Base::Fd(fd) => {
+                let inode = self.fstat(fd)?;
+                match inode.is_dir() {
+                    true => Ok(inode.ino),
+                    false => Err(ENOTDIR),
+                }
+            }
+        }
+    }
+
+    /// Walk the given path components, starting at `ino`.
+    fn walk<'p>(&self, mut ino: u64, components: impl Iterator<Item = &'p [u8]>) -> FsResult<u64> {
+        for name in components {
+            ino = match name {
+                b"" | b"." => {
+                    self.inodes[&ino].entries()?;
+                    ino
+                }
+                b".." => match &self.inodes[&ino].kind {
+                    InodeKind::Dir { parent, .. } => *parent,
+                    InodeKind::File(_) => return Err(ENOTDIR),
+                },
+                _ => self.lookup(ino, name)?.ok_or(ENOENT)?,
+            };
+        }
+        Ok(ino)
+    }
+
+    fn resolve(&self, base: Base, path: &[u8]) -> FsResult<u64> {
+        if path.is_empty() {
+            return Err(ENOENT);
+        }
+
+        let start = self.base_ino(base, path)?;
+        self.walk(start, path.split(|b| *b == b'/'))
+    }
+
+    /// Resolve the parent directory of a path, returning it along with the final component.
+    fn resolve_parent<'p>(&self, base: Base, path: &'p [u8]) -> FsResult<(u64, &'p [u8])> {
+        let trimmed = match path.iter().rposition(|b| *b != b'/') {
+            Some(end) => &path[..=end],
+            None if path.is_empty() => return Err(ENOENT),
+            // The root directory has no parent.
+            None => return Err(EEXIST),
+        };
+
+        let (dir, name) = match trimmed.iter().rposition(|b| *b == b'/') {
+            Some(i) => (&trimmed[..=i], &trimmed[i + 1..]),
+            None => (&b""[..], trimmed),
+        };
+        if name == b"." || name == b".." {
+            return Err(EINVAL);
+        }
+
+        let start = self.base_ino(base, path)?;
+        let parent = self.walk(start, dir.split(|b| *b == b'/'))?;
+        self.inodes[&parent].entries()?;
+        Ok((parent, name))
+    }
+}
@@ -0,0 +1,66 @@
+//! Tests for the simulated clocks.
+
+mod common;
+
+/// Run a test scene, asserting that it succeeds.
+fn test_success(scene: &str) {
+    let output = common::run_test_scene(scene);
+    assert!(output.status.success(), "{output}");
+}
+
+#[test]
+fn clock_ids() {
+    test_success("clock::clock_ids");
+}
+
+#[test]
+fn invalid_clock_id() {
+    test_success("clock::invalid_clock_id");
+}
+
+#[test]
+fn scene_epoch() {
+    test_success("clock::scene_epoch");
+}
+
+#[test]
+fn skew() {
+    test_success("clock::skew");
+}
+
+#[test]
+fn drift() {
+    test_success("clock::drift");
+}
+
+#[test]
+fn invalid_random_drift() {
+    let output = common::run_test_scene("clock::invalid_random_drift");
+    assert!(!output.status.success(), "{output}");
+    assert!(
+        output
+            .stderr
+            .contains("maximum clock drift must be at least 0"),
+        "{output}"
+    );
+}
+
+#[test]
+fn clock_jump() {
+    test_success("clock::clock_jump");
+}
+
+#[test]
+fn set_after_sleep() {
+    test_success("clock::set_after_sleep");
+}
+
+#[test]
+fn epoch_flag_overrides_scene() {
+    let output = common::run_test_scene_with_args(
+        "clock::print_epoch",
+        &["--epoch", "2030-06-01T00:00:00Z"],
+    );
+    assert!(output.status.success(), "{output}");
+    assert_eq!(output.stdout, "1906502400");
+}
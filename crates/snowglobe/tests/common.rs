@@ -1,7 +1,8 @@
 #![allow(dead_code)]
 
-use std::fmt;
-use std::process::{Command, ExitStatus};
+use std::path::PathBuf;
+use std::process::{self, Command, ExitStatus};
+use std::{env, fmt};
 
 #[derive(Debug)]
 pub struct SceneOutput {
@@ -29,11 +30,19 @@ impl fmt::Display for SceneOutput {
 }
 
 pub fn run_test_scene(scene: &str) -> SceneOutput {
+    run_test_scene_with_args(scene, &[])
+}
+
+pub fn run_test_scene_with_args(scene: &str, args: &[&str]) -> SceneOutput {
+    run_test_scenes(&[&["run", scene, "--rng-seed", "0"], args].concat())
+}
+
+/// Run the test scene bundle with the given command line.
+pub fn run_test_scenes(args: &[&str]) -> SceneOutput {
     let mut cmd = Command::new("cargo");
     cmd.args(["run", "--example", "test-scenes"])
         .arg("--")
-        .args(["run", scene])
-        .args(["--rng-seed", "0"]);
+        .args(args);
 
     let output = cmd.output().unwrap();
 
@@ -43,3 +52,8 @@ pub fn run_test_scene(scene: &str) -> SceneOutput {
         stderr: String::from_utf8(output.stderr).unwrap(),
     }
 }
+
+/// A path in the temporary directory that is unique to this test process.
+pub fn temp_path(name: &str) -> PathBuf {
+    env::temp_dir().join(format!("snowglobe-test-{}-{name}", process::id()))
+}
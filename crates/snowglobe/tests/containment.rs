@@ -20,4 +20,28 @@ macro_rules! test {
 }
 
 test!(thread_spawn, "Operation not permitted");
-test!(tokio_spawn_blocking, "Operation not permitted");
+test!(tokio_spawn_blocking, "use `#[scene(threads = \"green\")]`");
+test!(
+    tokio_block_in_place,
+    "can call blocking only when running on the multi-threaded runtime"
+);
+test!(thread_sleep, "is not permitted in this simulation");
+test!(libc_usleep, "`usleep` is not permitted");
+test!(fork, "`fork` is not permitted in this simulation");
+test!(execve, "`execve` is not permitted in this simulation");
+test!(system, "`system` is not permitted in this simulation");
+test!(
+    posix_spawn,
+    "`posix_spawn` is not permitted in this simulation"
+);
+test!(command, "subprocesses run outside of it");
+test!(
+    sleep_outside_host,
+    "is not permitted outside of a simulated host"
+);
+
+#[test]
+fn sleep_advance() {
+    let output = common::run_test_scene("containment::sleep_advance");
+    assert!(output.status.success(), "{output}");
+}
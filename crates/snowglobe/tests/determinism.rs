@@ -35,7 +35,25 @@ test!(select_branch);
 test!(hashset_order);
 test!(tokio_time);
 test!(std_time);
+test!(gettimeofday);
+test!(time);
+test!(clock_ticks);
+test!(timespec_get_utc);
+test!(ftime_timeb);
+test!(clock_getres);
+test!(clock_skew);
+test!(fs_metadata);
+test!(fs_crash);
+test!(dev_urandom);
+test!(libc_rand);
+#[cfg(target_os = "linux")]
+test!(green_threads);
+#[cfg(target_os = "linux")]
+test!(spawn_blocking);
 test!(uuid);
 test!(heap_address);
 test!(heap_address_ffi);
+test!(heap_address_realloc);
 test!(openssl_rand_bytes);
+test!(nemesis);
+test!(buggify);
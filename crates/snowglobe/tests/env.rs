@@ -0,0 +1,25 @@
+//! Tests for the environment seen by scenes and simulated hosts.
+
+mod common;
+
+/// Run a test scene, asserting that it succeeds.
+fn test_success(scene: &str) {
+    let output = common::run_test_scene(scene);
+    assert!(output.status.success(), "{output}");
+}
+
+macro_rules! test {
+    ($name:ident) => {
+        #[test]
+        fn $name() {
+            let scene = concat!("env::", stringify!($name));
+            test_success(scene);
+        }
+    };
+}
+
+test!(scene_env);
+test!(empty_by_default);
+test!(host_env);
+test!(process_env);
+test!(libc_setenv);
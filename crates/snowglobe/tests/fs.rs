@@ -0,0 +1,40 @@
+//! Tests for the simulated filesystem.
+
+mod common;
+
+/// Run a test scene, asserting that it succeeds.
+fn test_success(scene: &str) {
+    let output = common::run_test_scene(scene);
+    assert!(output.status.success(), "{output}");
+}
+
+macro_rules! test {
+    ($name:ident) => {
+        #[test]
+        fn $name() {
+            let scene = concat!("fs::", stringify!($name));
+            test_success(scene);
+        }
+    };
+}
+
+test!(file_roundtrip);
+test!(host_isolation);
+test!(no_real_disk);
+test!(survives_restart);
+test!(synced_survives_crash);
+test!(unsynced_lost_on_crash);
+test!(dir_used_outside_host);
+test!(working_directory);
+test!(paths);
+test!(dup);
+test!(disk_errors_per_host);
+test!(short_io);
+test!(disk_latency);
+
+#[test]
+fn disk_full() {
+    let output = common::run_test_scene("fs::disk_full");
+    assert!(output.status.success(), "{output}");
+    assert!(output.stderr.contains("disk fault injected"), "{output}");
+}
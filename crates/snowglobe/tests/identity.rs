@@ -0,0 +1,28 @@
+//! Tests for the process and system identity seen by simulated hosts.
+
+mod common;
+
+/// Run a test scene, asserting that it succeeds.
+fn test_success(scene: &str) {
+    let output = common::run_test_scene(scene);
+    assert!(output.status.success(), "{output}");
+}
+
+macro_rules! test {
+    ($name:ident) => {
+        #[test]
+        fn $name() {
+            let scene = concat!("identity::", stringify!($name));
+            test_success(scene);
+        }
+    };
+}
+
+test!(pids);
+test!(pid_survives_restart);
+test!(hostnames);
+test!(own_subscriber);
+test!(users);
+test!(default_cpus);
+test!(scene_cpus);
+test!(invalid_arguments);
@@ -0,0 +1,35 @@
+//! Tests for the log output of scene bundles.
+
+mod common;
+
+fn run_host_lines(log_format: &str) -> String {
+    let output = common::run_test_scenes(&[
+        "--log-format",
+        log_format,
+        "run",
+        "log::host_lines",
+        "--rng-seed",
+        "0",
+    ]);
+    assert!(output.status.success(), "{output}");
+
+    output.stderr
+}
+
+#[test]
+fn text() {
+    let stderr = run_host_lines("text");
+    let expected = "1.500000s  INFO client test_scenes::log: hello from client";
+    assert!(stderr.contains(expected), "{stderr}");
+}
+
+#[test]
+fn json() {
+    let stderr = run_host_lines("json");
+    let line = stderr
+        .lines()
+        .find(|l| l.contains("hello from client"))
+        .unwrap_or_else(|| panic!("{stderr}"));
+    assert!(line.contains(r#""host":"client""#), "{line}");
+    assert!(line.contains(r#""sim_time":1.5,"#), "{line}");
+}
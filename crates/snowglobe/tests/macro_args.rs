@@ -21,3 +21,9 @@ macro_rules! test {
 test!(bare);
 test!(durations);
 test!(rates);
+test!(epoch);
+test!(sleep);
+test!(disk);
+test!(env);
+test!(cpus);
+test!(threads);
@@ -0,0 +1,51 @@
+//! Tests for the property assertion macros.
+
+mod common;
+
+use std::fs;
+
+use snowglobe_proto::Message as _;
+use snowglobe_proto::{PropertyKind, Report};
+
+#[test]
+fn coverage() {
+    let path = common::temp_path("property-coverage.json");
+    let output = common::run_test_scene_with_args(
+        "property::coverage",
+        &["--report", path.to_str().unwrap()],
+    );
+    assert!(output.status.success(), "{output}");
+
+    let report = Report::deserialize(&fs::read(&path).unwrap()).unwrap();
+    let _ = fs::remove_file(&path);
+    let find = |message: &str| {
+        report
+            .properties
+            .iter()
+            .find(|p| p.message == message)
+            .unwrap_or_else(|| panic!("missing property: {message}"))
+    };
+
+    let p = find("index in bounds");
+    assert_eq!((p.kind, p.hits, p.passes), (PropertyKind::Always, 10, 10));
+    let p = find("index is five");
+    assert_eq!((p.kind, p.hits, p.passes), (PropertyKind::Sometimes, 10, 1));
+    let p = find("index out of bounds");
+    assert_eq!((p.kind, p.hits, p.passes), (PropertyKind::Sometimes, 10, 0));
+    let p = find("first iteration");
+    assert_eq!((p.kind, p.hits, p.passes), (PropertyKind::Reachable, 1, 1));
+    let p = find("index below five");
+    assert_eq!((p.kind, p.hits, p.passes), (PropertyKind::Always, 0, 0));
+}
+
+#[test]
+fn always_violated() {
+    let output = common::run_test_scene("property::always_violated");
+    assert!(!output.status.success(), "{output}");
+    assert!(
+        output
+            .stderr
+            .contains("property violated: index below five"),
+        "{output}"
+    );
+}
@@ -0,0 +1,143 @@
+//! Tests for the `Sim` API.
+
+mod common;
+
+/// Run a test scene, asserting that it succeeds.
+fn test_success(scene: &str) {
+    let output = common::run_test_scene(scene);
+    assert!(output.status.success(), "{output}");
+}
+
+/// Run a test scene, asserting that it fails and reports the expected message.
+fn test_failure(scene: &str, message: &str) {
+    let output = common::run_test_scene(scene);
+    assert!(!output.status.success(), "{output}");
+    assert!(output.stderr.contains(message), "{output}");
+}
+
+#[test]
+fn network_faults() {
+    test_success("sim::network_faults");
+}
+
+#[test]
+fn failure_during_partition() {
+    test_failure("sim::failure_during_partition", "a -> b");
+}
+
+#[test]
+fn host_lifecycle() {
+    test_success("sim::host_lifecycle");
+}
+
+#[test]
+fn restart_after_twice() {
+    test_success("sim::restart_after_twice");
+}
+
+#[test]
+fn link_config() {
+    test_success("sim::link_config");
+}
+
+#[test]
+fn nemesis() {
+    test_success("sim::nemesis");
+}
+
+#[test]
+fn invariant_holds() {
+    test_success("sim::invariant_holds");
+}
+
+#[test]
+fn invariant_violated() {
+    test_failure(
+        "sim::invariant_violated",
+        "invariant `counter_below_five` violated at 41ms (seed 0)",
+    );
+}
+
+#[test]
+fn run_phases() {
+    test_success("sim::run_phases");
+}
+
+#[test]
+fn run_after_finish() {
+    test_success("sim::run_after_finish");
+}
+
+#[test]
+fn quiescent_while_held() {
+    test_success("sim::quiescent_while_held");
+}
+
+#[test]
+fn message_tap() {
+    test_success("sim::message_tap");
+}
+
+#[test]
+fn message_kinds() {
+    test_success("sim::message_kinds");
+}
+
+#[test]
+fn message_capture() {
+    use snowglobe_proto::Message as _;
+    use snowglobe_proto::{MessageEvent, MessageEventKind};
+
+    let path = common::temp_path("message-capture.jsonl");
+    let output = common::run_test_scene_with_args(
+        "sim::message_tap",
+        &["--capture", path.to_str().unwrap()],
+    );
+    assert!(output.status.success(), "{output}");
+
+    let capture = std::fs::read_to_string(&path).unwrap();
+    let _ = std::fs::remove_file(&path);
+    let events: Vec<_> = capture
+        .lines()
+        .map(|l| MessageEvent::deserialize(l.as_bytes()).unwrap())
+        .collect();
+    let sent = events
+        .iter()
+        .filter(|e| e.kind == MessageEventKind::Sent)
+        .count();
+    assert_eq!(sent, 3);
+    assert!(events.iter().all(|e| e.dst.ends_with(":9000")));
+}
+
+#[test]
+fn event_sink() {
+    use snowglobe_proto::Message as _;
+    use snowglobe_proto::{Event, EventKind};
+
+    let path = common::temp_path("events.jsonl");
+    let output = common::run_test_scene_with_args(
+        "sim::network_faults",
+        &["--events", path.to_str().unwrap()],
+    );
+    assert!(output.status.success(), "{output}");
+
+    let events = std::fs::read_to_string(&path).unwrap();
+    let _ = std::fs::remove_file(&path);
+    let kinds: Vec<_> = events
+        .lines()
+        .map(|l| Event::deserialize(l.as_bytes()).unwrap().kind)
+        .collect();
+    assert_eq!(kinds.len(), 6, "{kinds:?}");
+    assert!(matches!(&kinds[0], EventKind::Partition { a, b } if a == "a" && b == "b"));
+}
+
+/// Failing to write events fails the run once it is over, rather than panicking in it.
+#[cfg(target_os = "linux")]
+#[test]
+fn event_sink_error() {
+    let output =
+        common::run_test_scene_with_args("sim::network_faults", &["--events", "/dev/full"]);
+    assert!(!output.status.success(), "{output}");
+    assert!(output.stderr.contains("writing event failed"), "{output}");
+    assert!(!output.stderr.contains("panicked"), "{output}");
+}
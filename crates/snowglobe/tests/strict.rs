@@ -0,0 +1,47 @@
+//! Tests for strict mode, which traps system calls that bypass the patches.
+
+// Strict mode is built on seccomp, which only Linux has.
+#![cfg(target_os = "linux")]
+
+mod common;
+
+fn run_strict(scene: &str) -> common::SceneOutput {
+    common::run_test_scene_with_args(scene, &["--strict"])
+}
+
+#[test]
+fn raw_syscall_trapped() {
+    let output = run_strict("containment::raw_getrandom");
+    assert!(!output.status.success(), "{output}");
+    assert!(
+        output.stderr.contains("`getrandom` (syscall")
+            && output.stderr.contains("bypassed snowglobe's patches"),
+        "{output}"
+    );
+    assert!(output.stderr.contains("backtrace:"), "{output}");
+}
+
+#[test]
+fn raw_syscall_unnoticed_by_default() {
+    let output = common::run_test_scene("containment::raw_getrandom");
+    assert!(output.status.success(), "{output}");
+}
+
+#[test]
+fn patched_calls() {
+    for scene in [
+        "determinism::random_numbers",
+        "determinism::std_time",
+        "identity::hostnames",
+        "sim::network_faults",
+    ] {
+        let output = run_strict(scene);
+        assert!(output.status.success(), "{scene}: {output}");
+    }
+}
+
+#[test]
+fn green_threads() {
+    let output = run_strict("threads::spawn_blocking");
+    assert!(output.status.success(), "{output}");
+}
@@ -0,0 +1,43 @@
+//! Tests for green threads.
+
+// Green threads are scheduled through the futex system call, which only Linux has.
+#![cfg(target_os = "linux")]
+
+mod common;
+
+/// Run a test scene, asserting that it succeeds.
+fn test_success(scene: &str) {
+    let output = common::run_test_scene(scene);
+    assert!(output.status.success(), "{output}");
+}
+
+macro_rules! test {
+    ($name:ident) => {
+        #[test]
+        fn $name() {
+            let scene = concat!("threads::", stringify!($name));
+            test_success(scene);
+        }
+    };
+}
+
+test!(spawn_join);
+test!(thread_ids);
+test!(panic_join);
+test!(channel);
+test!(mutex_condvar);
+test!(background_thread);
+test!(thread_dies_with_host);
+test!(spawn_blocking);
+test!(tokio_fs);
+test!(spawn_blocking_crash);
+
+#[test]
+fn deadlock() {
+    let output = common::run_test_scene("threads::deadlock");
+    assert!(!output.status.success(), "{output}");
+    assert!(
+        output.stderr.contains("all threads are blocked"),
+        "{output}"
+    );
+}